@@ -14,7 +14,8 @@ export interface IlNode {
   id: number
   uri: String
   breed: IlNodeType
-  children?: [IlNode]
-  parent: number
+  // how many children the node has, they are fetched with `get_children`
+  children?: number
+  parent?: number
   visible: boolean
 }
@@ -0,0 +1,305 @@
+//! The commands of the desktop app
+
+use crate::{
+    arena::NodeId,
+    changes::ChangeSet,
+    client::{ClientError, Credentials},
+    conflicts::{ConflictEntry, Resolution},
+    exercise::Deadline,
+    forum::Thread,
+    ilias::{IliasTree, NodeView},
+    instance::IliasInstance,
+    integrity::IntegrityIssue,
+    policy::SyncPolicy,
+    progress::SyncStatus,
+    search::{self, SearchHit},
+    settings::{Quality, Settings},
+    submission::SubmittedFile,
+    tree::{CourseFilter, TreeError},
+    versions::FileVersion,
+    video::VideoInfo,
+};
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+use tauri::{generate_context, Emitter};
+
+#[tauri::command]
+async fn login(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    creds: Credentials,
+) -> Result<(), ClientError> {
+    ilias.login(creds).await
+}
+
+#[tauri::command]
+async fn login_cached(ilias: tauri::State<'_, Arc<IliasTree>>) -> Result<(), String> {
+    ilias.login_cached().await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn is_authenticated(ilias: tauri::State<'_, Arc<IliasTree>>) -> bool {
+    ilias.is_authenticated()
+}
+
+#[tauri::command]
+async fn update_root(ilias: tauri::State<'_, Arc<IliasTree>>) -> Result<ChangeSet, TreeError> {
+    ilias.update_root().await
+}
+
+#[tauri::command]
+fn sync_status(ilias: tauri::State<'_, Arc<IliasTree>>) -> SyncStatus {
+    ilias.progress().status()
+}
+
+#[tauri::command]
+fn recent_changes(ilias: tauri::State<'_, Arc<IliasTree>>) -> Vec<ChangeSet> {
+    ilias.recent_changes()
+}
+
+/// The root without its children, they come from `get_children`
+#[tauri::command]
+fn get_root(ilias: tauri::State<'_, Arc<IliasTree>>) -> NodeView {
+    NodeView::from(&ilias.get_root_node())
+}
+
+#[tauri::command]
+fn get_node(ilias: tauri::State<'_, Arc<IliasTree>>, id: NodeId) -> Result<NodeView, String> {
+    ilias.view(id).ok_or_else(|| unknown(id))
+}
+
+/// The children of the node with `id`, fetched when it is opened
+#[tauri::command]
+fn get_children(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+) -> Result<Vec<NodeView>, String> {
+    ilias.children(id).ok_or_else(|| unknown(id))
+}
+
+fn unknown(id: NodeId) -> String {
+    format!("no node with id {id}")
+}
+
+#[tauri::command]
+async fn download(ilias: tauri::State<'_, Arc<IliasTree>>, id: NodeId) -> Result<(), String> {
+    ilias.download(id).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn video_info(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+) -> Result<VideoInfo, String> {
+    ilias.video_info(id).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn download_video(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+    quality: Option<Quality>,
+) -> Result<(), String> {
+    ilias
+        .download_video(id, quality)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn file_versions(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+) -> Result<Vec<FileVersion>, String> {
+    ilias.file_versions(id).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn restore_version(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+    version: usize,
+) -> Result<(), String> {
+    ilias
+        .restore_version(id, version)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn verify(ilias: tauri::State<'_, Arc<IliasTree>>) -> Result<Vec<IntegrityIssue>, String> {
+    let tree = ilias.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || tree.verify())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn list_conflicts(ilias: tauri::State<'_, Arc<IliasTree>>) -> Vec<ConflictEntry> {
+    ilias.conflicts()
+}
+
+#[tauri::command]
+fn resolve_conflict(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+    resolution: Resolution,
+) -> Result<(), String> {
+    ilias
+        .resolve_conflict(id, resolution)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn set_policy(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+    policy: Option<SyncPolicy>,
+) -> Result<(), String> {
+    ilias.set_policy(id, policy).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn deadlines(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    from: Option<DateTime<Local>>,
+    until: Option<DateTime<Local>>,
+) -> Vec<Deadline> {
+    ilias.deadlines(from, until)
+}
+
+#[tauri::command]
+async fn submit(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+    files: Vec<PathBuf>,
+) -> Result<Vec<SubmittedFile>, String> {
+    ilias
+        .submit(id, &files)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Nodes matching `query`, best first
+#[tauri::command]
+fn search(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    query: String,
+    limit: Option<usize>,
+) -> Vec<SearchHit> {
+    ilias.search(&query, limit.unwrap_or(search::DEFAULT_LIMIT))
+}
+
+/// Writes the calendar of the `courses` (titles or uris), of all courses if empty
+#[tauri::command]
+fn export_calendar(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    path: PathBuf,
+    courses: Vec<String>,
+) -> Result<usize, String> {
+    let filter = match courses.is_empty() {
+        true => CourseFilter::All,
+        false => CourseFilter::Only(courses.into_iter().collect::<HashSet<_>>()),
+    };
+    ilias
+        .export_calendar(&path, &filter)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn forum_threads(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    id: NodeId,
+) -> Result<Vec<Thread>, String> {
+    ilias.forum_threads(id).map_err(|err| err.to_string())
+}
+
+/// Threads aren't nodes of the tree, they are addressed by their `uri`
+#[tauri::command]
+fn mark_read(
+    ilias: tauri::State<'_, Arc<IliasTree>>,
+    uri: String,
+    post: Option<String>,
+    read: bool,
+) -> Result<(), String> {
+    ilias
+        .mark_read(&uri, post.as_deref(), read)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn export_thread(ilias: tauri::State<'_, Arc<IliasTree>>, uri: String) -> Result<PathBuf, String> {
+    ilias.export_thread(&uri).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_instance(ilias: tauri::State<'_, Arc<IliasTree>>) -> IliasInstance {
+    ilias.instance().clone()
+}
+
+#[tauri::command]
+fn open(path: PathBuf) -> Result<(), String> {
+    match open::that(&path) {
+        Ok(_) => {
+            info!("{path:?}");
+            Ok(())
+        }
+        Err(e) => {
+            warn!("{e:?}");
+            Err(e.to_string())
+        }
+    }
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub async fn run() {
+    env_logger::init();
+
+    let tree = Arc::new(IliasTree::new(IliasInstance::load(), Settings::load()).await);
+    let tree_clone = tree.clone();
+    let app = tauri::Builder::default()
+        .manage(tree)
+        .invoke_handler(tauri::generate_handler![
+            login,
+            login_cached,
+            is_authenticated,
+            update_root,
+            sync_status,
+            recent_changes,
+            get_root,
+            get_node,
+            get_children,
+            download,
+            video_info,
+            download_video,
+            file_versions,
+            restore_version,
+            verify,
+            list_conflicts,
+            resolve_conflict,
+            set_policy,
+            deadlines,
+            submit,
+            export_calendar,
+            search,
+            forum_threads,
+            mark_read,
+            export_thread,
+            get_instance,
+            open
+        ])
+        .build(generate_context!())
+        .expect("error while running tauri application");
+
+    let handle = app.handle().clone();
+    tree_clone.progress().set_listener(move |event| {
+        if let Err(err) = handle.emit("sync-progress", event) {
+            warn!("can't emit progress: {err}");
+        }
+    });
+
+    app.run(move |_app_handle, e| {
+        if let tauri::RunEvent::Exit { .. } = e {
+            tree_clone.save().map_err(|err| warn!("{err}")).ok();
+        }
+    });
+}
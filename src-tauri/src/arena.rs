@@ -0,0 +1,244 @@
+//! Ids for the nodes of the tree.
+//!
+//! The nodes stay shared `Arc<Mutex<IlNode>>` owning their children: the crawler, the
+//! downloads and the commands all hold on to them. The arena doesn't own the nodes, it
+//! indexes them by id and uri and links every node to its parent, so commands address
+//! nodes by id without searching the tree. A sync keeps it up to date while it replaces
+//! the children of the nodes it crawls, see [NodeArena::set_children].
+
+use crate::ilias::{walk, WrappedNode};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+/// Identifies a node across syncs and restarts, `0` until the node was indexed
+pub type NodeId = u64;
+
+/// The nodes of the tree by id and uri, every node linked to its parent
+#[derive(Default)]
+pub struct NodeArena {
+    nodes: HashMap<NodeId, WrappedNode>,
+    /// Ids of the nodes with each uri in the order they were indexed,
+    /// a listing may show the same uri twice
+    ids: HashMap<String, Vec<NodeId>>,
+    next_id: NodeId,
+}
+
+impl NodeArena {
+    pub fn new(root: &WrappedNode) -> Self {
+        let mut arena = Self::default();
+        arena.index(root);
+        arena
+    }
+
+    /// Indexes every node below `root` again.
+    /// Nodes keep their id, new ones get the next free one.
+    pub fn index(&mut self, root: &WrappedNode) {
+        let mut highest = 0;
+        walk(root, &mut |_, node| highest = highest.max(node.id));
+        // ids of nodes removed since the last index aren't given out again
+        self.next_id = self.next_id.max(highest + 1);
+        self.nodes.clear();
+        self.ids.clear();
+        self.visit(root, None);
+    }
+
+    fn visit(&mut self, node: &WrappedNode, parent: Option<NodeId>) {
+        let (id, children) = {
+            let mut guard = node.lock().unwrap();
+            if guard.id == 0 {
+                guard.id = self.next_id;
+                self.next_id += 1;
+            }
+            guard.parent = parent;
+            let ids = self.ids.entry(guard.uri.clone()).or_default();
+            if !ids.contains(&guard.id) {
+                ids.push(guard.id);
+            }
+            (guard.id, guard.children.clone().unwrap_or_default())
+        };
+        self.nodes.insert(id, node.clone());
+        for child in &children {
+            self.visit(child, Some(id));
+        }
+    }
+
+    /// Drops `node` and everything below it from the index
+    fn forget(&mut self, node: &WrappedNode) {
+        let (id, uri, children) = {
+            let guard = node.lock().unwrap();
+            (guard.id, guard.uri.clone(), guard.children.clone())
+        };
+        self.nodes.remove(&id);
+        if let Some(ids) = self.ids.get_mut(&uri) {
+            ids.retain(|known| *known != id);
+            if ids.is_empty() {
+                self.ids.remove(&uri);
+            }
+        }
+        for child in children.iter().flatten() {
+            self.forget(child);
+        }
+    }
+
+    /// Replaces the children of `parent`. Children that aren't listed anymore are
+    /// forgotten with everything below them, new ones get the next free ids.
+    pub fn set_children(&mut self, parent: &WrappedNode, children: Option<Vec<WrappedNode>>) {
+        let (id, old) = {
+            let mut guard = parent.lock().unwrap();
+            let old = std::mem::replace(&mut guard.children, children.clone());
+            (guard.id, old.unwrap_or_default())
+        };
+        let kept = children
+            .iter()
+            .flatten()
+            .map(Arc::as_ptr)
+            .collect::<HashSet<_>>();
+        let known = old.iter().map(Arc::as_ptr).collect::<HashSet<_>>();
+        for child in old
+            .iter()
+            .filter(|child| !kept.contains(&Arc::as_ptr(child)))
+        {
+            self.forget(child);
+        }
+        for child in children.iter().flatten() {
+            if !known.contains(&Arc::as_ptr(child)) {
+                self.visit(child, Some(id));
+            }
+        }
+    }
+
+    /// Indexes `node` under its uri after it changed from `from`
+    pub fn moved(&mut self, node: &WrappedNode, from: &str) {
+        let (id, uri) = {
+            let guard = node.lock().unwrap();
+            (guard.id, guard.uri.clone())
+        };
+        // nodes that aren't indexed yet are found by their new uri once they are
+        if !self
+            .nodes
+            .get(&id)
+            .is_some_and(|known| Arc::ptr_eq(known, node))
+        {
+            return;
+        }
+        if let Some(ids) = self.ids.get_mut(from) {
+            ids.retain(|known| *known != id);
+            if ids.is_empty() {
+                self.ids.remove(from);
+            }
+        }
+        self.ids.entry(uri).or_default().push(id);
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<WrappedNode> {
+        self.nodes.get(&id).cloned()
+    }
+
+    /// Id of the first node indexed with `uri`
+    pub fn id(&self, uri: &str) -> Option<NodeId> {
+        self.ids.get(uri)?.first().copied()
+    }
+
+    pub fn find(&self, uri: &str) -> Option<WrappedNode> {
+        self.get(self.id(uri)?)
+    }
+
+    /// The nodes from the root down to the parent of the node with `id`
+    pub fn ancestors(&self, id: NodeId) -> Vec<WrappedNode> {
+        let mut ancestors = vec![];
+        let mut parent = self.get(id).and_then(|node| node.lock().unwrap().parent);
+        while let Some(node) = parent.and_then(|id| self.get(id)) {
+            parent = node.lock().unwrap().parent;
+            ancestors.push(node);
+        }
+        ancestors.reverse();
+        ancestors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ilias::{IlNode, IlNodeType};
+    use std::sync::Mutex;
+
+    fn node(uri: &str, children: Vec<WrappedNode>) -> WrappedNode {
+        Arc::new(Mutex::new(IlNode {
+            uri: uri.to_string(),
+            title: uri.to_string(),
+            breed: IlNodeType::Group,
+            children: Some(children),
+            ..Default::default()
+        }))
+    }
+
+    fn id(node: &WrappedNode) -> NodeId {
+        node.lock().unwrap().id
+    }
+
+    #[test]
+    fn links_nodes_to_their_parents() {
+        let file = node("file", vec![]);
+        let folder = node("folder", vec![file.clone()]);
+        let root = node("root", vec![folder.clone()]);
+        let arena = NodeArena::new(&root);
+
+        assert_eq!(arena.id("file"), Some(id(&file)));
+        assert!(Arc::ptr_eq(&arena.find("folder").unwrap(), &folder));
+        let ancestors = arena.ancestors(id(&file));
+        assert_eq!(ancestors.len(), 2);
+        assert!(Arc::ptr_eq(&ancestors[0], &root));
+        assert!(Arc::ptr_eq(&ancestors[1], &folder));
+    }
+
+    #[test]
+    fn replacing_children_keeps_ids_and_forgets_removed_nodes() {
+        let kept = node("kept", vec![]);
+        let nested = node("nested", vec![]);
+        let removed = node("removed", vec![nested.clone()]);
+        let root = node("root", vec![kept.clone(), removed.clone()]);
+        let mut arena = NodeArena::new(&root);
+        let (kept_id, removed_id, nested_id) = (id(&kept), id(&removed), id(&nested));
+
+        let added = node("added", vec![]);
+        arena.set_children(&root, Some(vec![kept.clone(), added.clone()]));
+        assert_eq!(id(&kept), kept_id);
+        assert!(arena.get(removed_id).is_none());
+        assert!(arena.get(nested_id).is_none());
+        assert_eq!(arena.id("removed"), None);
+        assert!(id(&added) > nested_id);
+        assert_eq!(arena.ancestors(id(&added)).len(), 1);
+
+        // ids of removed nodes aren't given out again
+        arena.index(&root);
+        arena.set_children(&root, Some(vec![kept, added, node("new", vec![])]));
+        assert!(arena.id("new").unwrap() > arena.id("added").unwrap());
+    }
+
+    #[test]
+    fn keeps_every_node_with_the_same_uri() {
+        let first = node("dup", vec![]);
+        let second = node("dup", vec![]);
+        let root = node("root", vec![first.clone(), second.clone()]);
+        let mut arena = NodeArena::new(&root);
+        assert_ne!(id(&first), id(&second));
+        assert_eq!(arena.id("dup"), Some(id(&first)));
+        assert!(arena.get(id(&second)).is_some());
+
+        arena.set_children(&root, Some(vec![second.clone()]));
+        assert_eq!(arena.id("dup"), Some(id(&second)));
+    }
+
+    #[test]
+    fn follows_changed_uris() {
+        let link = node("goto.php?target=webr_1", vec![]);
+        let root = node("root", vec![link.clone()]);
+        let mut arena = NodeArena::new(&root);
+        link.lock().unwrap().uri = "https://example.org".to_string();
+        arena.moved(&link, "goto.php?target=webr_1");
+        assert_eq!(arena.id("goto.php?target=webr_1"), None);
+        assert_eq!(arena.id("https://example.org"), Some(id(&link)));
+    }
+}
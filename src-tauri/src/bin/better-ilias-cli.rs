@@ -0,0 +1,684 @@
+//! Syncs ilias without the GUI, e.g. from cron:
+//! `better-ilias-cli --dir ~/uni sync --json`
+
+use chrono::{DateTime, Local, TimeDelta};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::{
+    env,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+};
+use tst_lib::{
+    arena::NodeId,
+    changes::{Change, ChangeSet},
+    client::{ClientError, Credentials},
+    daemon,
+    exercise::Submission,
+    forum::{self, Thread},
+    ilias::{IlNode, IlNodeType, IliasTree},
+    instance::IliasInstance,
+    policy::SyncPolicy,
+    progress::ProgressEvent,
+    search,
+    settings::{Quality, Settings},
+    tree::{CourseFilter, TreeError},
+};
+
+/// Read instead of prompting for the password, for unattended logins
+const PASSWORD_VAR: &str = "BETTER_ILIAS_PASSWORD";
+
+#[derive(Parser)]
+#[command(name = "better-ilias-cli", version, about)]
+struct Cli {
+    /// Print JSON instead of human readable output
+    #[arg(long, global = true)]
+    json: bool,
+    /// Directory the `studium/` folder is synced into, defaults to the current one
+    #[arg(short = 'C', long, global = true)]
+    dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log in and remember the credentials for later runs
+    Login {
+        /// Asked for if missing
+        #[arg(short, long)]
+        username: Option<String>,
+    },
+    /// Crawl all courses and download new files
+    Sync,
+    /// Keep running and sync on the schedules from the settings
+    Daemon,
+    /// What the last syncs changed, newest first
+    Changes {
+        /// How many syncs to show
+        #[arg(short = 'n', long, default_value_t = 5)]
+        limit: usize,
+    },
+    /// Print the whole tree
+    Tree,
+    /// List the children of a node
+    Ls {
+        /// Titles separated by `/`, the uri or the id of the node, defaults to the root
+        path: Option<String>,
+    },
+    /// Download a file that is only available remotely or the recording of a video
+    Download {
+        /// Titles separated by `/`, the uri or the id of the node
+        node: String,
+        /// For videos: `highest`, `lowest` or the maximum height like `720`
+        #[arg(short, long, value_parser = parse_quality)]
+        quality: Option<Quality>,
+    },
+    /// Open a file or folder with the default program
+    Open {
+        /// Titles separated by `/`, the uri or the id of the node
+        node: String,
+    },
+    /// Summary of what is synced locally
+    Status,
+    /// Set what the sync does with the files of a course or folder
+    Policy {
+        /// Titles separated by `/`, the uri or the id of the node
+        node: String,
+        policy: PolicyArg,
+    },
+    /// Assignments that are due soon
+    Deadlines {
+        /// How many days ahead to look
+        #[arg(short, long, default_value_t = 7)]
+        days: i64,
+    },
+    /// Hand in files for an assignment
+    Submit {
+        /// Titles separated by `/`, the uri or the id of the assignment
+        assignment: String,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Write the deadlines and sessions as iCalendar
+    Calendar {
+        /// File to write, e.g. `ilias.ics`
+        output: PathBuf,
+        /// Only these courses, by title or uri. Can be repeated.
+        #[arg(short, long)]
+        course: Vec<String>,
+    },
+    /// Search titles, descriptions and downloaded documents
+    Search {
+        /// Words that all have to match, the last one may be incomplete
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Show at most this many results
+        #[arg(short, long, default_value_t = search::DEFAULT_LIMIT)]
+        limit: usize,
+    },
+    /// List the threads of a forum with their unread posts
+    Threads {
+        /// Titles separated by `/`, the uri or the id of the forum
+        forum: String,
+    },
+    /// Print a thread as Markdown
+    Thread {
+        /// Titles separated by `/`, the uri or the id of the forum
+        forum: String,
+        /// Title or uri of the thread
+        thread: String,
+        /// Write it into the course folder instead
+        #[arg(long)]
+        export: bool,
+    },
+    /// Mark all posts of a thread as read
+    Read {
+        /// Titles separated by `/`, the uri or the id of the forum
+        forum: String,
+        /// Title or uri of the thread
+        thread: String,
+        /// Mark them as unread instead
+        #[arg(long)]
+        unread: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PolicyArg {
+    DownloadAll,
+    MetadataOnly,
+    Never,
+    /// Use the policy of the parent
+    Inherit,
+}
+
+/// Why a command failed, each with its own exit code
+enum Failure {
+    /// Exit code 1
+    Error(anyhow::Error),
+    /// Not logged in or ilias rejected the credentials, exit code 2
+    Auth(String),
+    /// No node matched, exit code 3
+    NotFound(String),
+    /// The sync finished but some files failed to download, exit code 4
+    Partial(usize),
+}
+
+impl Failure {
+    fn code(&self) -> u8 {
+        match self {
+            Failure::Error(_) => 1,
+            Failure::Auth(_) => 2,
+            Failure::NotFound(_) => 3,
+            Failure::Partial(_) => 4,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Failure::Error(err) => format!("{err:#}"),
+            Failure::Auth(msg) => msg.clone(),
+            Failure::NotFound(query) => format!("no node matches {query}"),
+            Failure::Partial(failed) => format!("{failed} files failed to download"),
+        }
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for Failure {
+    fn from(err: E) -> Self {
+        Failure::Error(err.into())
+    }
+}
+
+type CliResult = Result<(), Failure>;
+
+#[derive(Serialize)]
+struct Entry {
+    id: NodeId,
+    uri: String,
+    title: String,
+    kind: &'static str,
+    path: Option<PathBuf>,
+    ignored: bool,
+}
+
+impl Entry {
+    fn new(node: &IlNode) -> Self {
+        Self {
+            id: node.id,
+            uri: node.uri.clone(),
+            title: node.title.clone(),
+            kind: kind(&node.breed),
+            path: node.breed.local_path(),
+            ignored: node.ignored,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Status {
+    instance: String,
+    credentials_cached: bool,
+    courses: usize,
+    files: usize,
+    downloaded: usize,
+    conflicts: usize,
+}
+
+fn kind(breed: &IlNodeType) -> &'static str {
+    match breed {
+        IlNodeType::Forum => "forum",
+        IlNodeType::Folder { .. } => "folder",
+        IlNodeType::DirectLink => "link",
+        IlNodeType::File { .. } => "file",
+        IlNodeType::Video { .. } => "video",
+        IlNodeType::Exercise => "exercise",
+        IlNodeType::Assignment { .. } => "assignment",
+        IlNodeType::Session { .. } => "session",
+        IlNodeType::Group => "group",
+        IlNodeType::Root => "root",
+    }
+}
+
+fn parse_quality(quality: &str) -> Result<Quality, String> {
+    match quality {
+        "highest" => Ok(Quality::Highest),
+        "lowest" => Ok(Quality::Lowest),
+        height => height
+            .trim_end_matches('p')
+            .parse()
+            .map(Quality::AtMost)
+            .map_err(|_| format!("{quality} is neither highest, lowest nor a height")),
+    }
+}
+
+fn children(node: &IlNode) -> Vec<IlNode> {
+    node.children
+        .iter()
+        .flatten()
+        .map(|child| child.lock().unwrap().clone())
+        .collect()
+}
+
+/// Finds a node by its uri, by the titles on the way to it or by its id
+fn resolve(tree: &IliasTree, query: &str) -> Result<IlNode, Failure> {
+    if let Some(node) = tree.find_node(query) {
+        return Ok(node.lock().unwrap().clone());
+    }
+    let by_titles = query.split('/').filter(|title| !title.is_empty()).try_fold(
+        tree.get_root_node(),
+        |node, title| {
+            children(&node)
+                .into_iter()
+                .find(|child| child.title == title)
+        },
+    );
+    by_titles
+        .or_else(|| tree.get_node(query.parse().ok()?))
+        .ok_or_else(|| Failure::NotFound(query.to_string()))
+}
+
+fn deadlines(tree: &IliasTree, days: i64, json: bool) -> CliResult {
+    let now = Local::now();
+    let deadlines = tree.deadlines(Some(now), Some(now + TimeDelta::days(days)));
+    if json {
+        return print_json(&deadlines);
+    }
+    for deadline in deadlines {
+        let due = deadline
+            .info
+            .deadline
+            .map(|due| due.format("%a %d.%m. %H:%M").to_string())
+            .unwrap_or_default();
+        let submitted = match deadline.info.submission {
+            Submission::Submitted { .. } => "submitted",
+            Submission::Missing => "open",
+            Submission::Unknown => "",
+        };
+        println!(
+            "{due:<16} {:<30} {:<30} {submitted}",
+            deadline.course,
+            format!("{}: {}", deadline.exercise, deadline.title)
+        );
+    }
+    Ok(())
+}
+
+/// Finds a thread of the forum `forum` by its uri or title
+fn resolve_thread(tree: &IliasTree, forum: &str, query: &str) -> Result<(IlNode, Thread), Failure> {
+    let forum = resolve(tree, forum)?;
+    if forum.breed != IlNodeType::Forum {
+        return Err(anyhow::anyhow!("{} is not a forum", forum.title).into());
+    }
+    let thread = tree
+        .forum_threads(forum.id)?
+        .into_iter()
+        .find(|thread| thread.uri == query || thread.title == query)
+        .ok_or_else(|| Failure::NotFound(query.to_string()))?;
+    Ok((forum, thread))
+}
+
+fn threads(tree: &IliasTree, query: &str, json: bool) -> CliResult {
+    let forum = resolve(tree, query)?;
+    let threads = tree.forum_threads(forum.id)?;
+    if json {
+        return print_json(&threads);
+    }
+    for thread in threads {
+        let posts = thread.posts.len();
+        println!(
+            "{:<50} {posts:>3} posts, {} unread",
+            thread.title,
+            thread.unread()
+        );
+    }
+    Ok(())
+}
+
+fn thread(tree: &IliasTree, forum: &str, query: &str, export: bool, json: bool) -> CliResult {
+    let (forum, thread) = resolve_thread(tree, forum, query)?;
+    if export {
+        let path = tree.export_thread(&thread.uri)?;
+        if json {
+            return print_json(&path);
+        }
+        println!("exported to {}", path.display());
+    } else if json {
+        return print_json(&thread);
+    } else {
+        let markdown = forum::to_markdown(&forum.title, &thread, tree.instance());
+        print!("{markdown}");
+    }
+    Ok(())
+}
+
+fn print_json(value: &impl Serialize) -> CliResult {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
+fn print_tree(node: &IlNode, depth: usize) {
+    let suffix = match node.breed {
+        IlNodeType::Folder { .. } | IlNodeType::Root => "/",
+        _ => "",
+    };
+    let ignored = if node.ignored { " (ignored)" } else { "" };
+    println!("{}{}{suffix}{ignored}", "  ".repeat(depth), node.title);
+    for child in children(node) {
+        print_tree(&child, depth + 1);
+    }
+}
+
+async fn login(tree: &IliasTree, username: Option<String>) -> CliResult {
+    let name = match username {
+        Some(name) => name,
+        None => {
+            eprint!("Username: ");
+            io::stderr().flush()?;
+            let mut name = String::new();
+            io::stdin().read_line(&mut name)?;
+            name.trim().to_string()
+        }
+    };
+    let pw = match env::var(PASSWORD_VAR) {
+        Ok(pw) => pw,
+        Err(_) => rpassword::prompt_password("Password: ")?,
+    };
+    match tree.login(Credentials::new(name, pw)).await {
+        Ok(()) => Ok(()),
+        Err(ClientError::BadCredentials) => {
+            Err(Failure::Auth(ClientError::BadCredentials.to_string()))
+        }
+        Err(err) => Err(Failure::Error(err.into())),
+    }
+}
+
+async fn login_cached(tree: &IliasTree) -> CliResult {
+    tree.login_cached().await.map_err(|err| {
+        Failure::Auth(format!(
+            "can't log in with the stored credentials, run `better-ilias-cli login` first: {err}"
+        ))
+    })
+}
+
+/// Prints the progress of downloads, as JSON lines on stderr with `json`
+/// so stdout only holds the result
+fn print_progress(tree: &IliasTree, json: bool) {
+    tree.progress().set_listener(move |event| {
+        if json {
+            if let Ok(line) = serde_json::to_string(event) {
+                eprintln!("{line}");
+            }
+            return;
+        }
+        match event {
+            ProgressEvent::FileDone { path, .. } => println!("downloaded {}", path.display()),
+            ProgressEvent::FileFailed { uri, error } => eprintln!("failed {uri}: {error}"),
+            ProgressEvent::Finished { completed, failed } => {
+                println!("{completed} files downloaded, {failed} failed")
+            }
+            _ => {}
+        }
+    });
+}
+
+async fn sync(tree: &IliasTree, json: bool) -> CliResult {
+    login_cached(tree).await?;
+    print_progress(tree, json);
+
+    let result = tree.update_root().await;
+    // keep what was crawled even if the sync was cut short
+    tree.save()?;
+    match result {
+        Ok(changes) if json => print_json(&changes)?,
+        Ok(changes) => print_changes(&changes),
+        Err(TreeError::Client(
+            err
+            @ (ClientError::NoToken | ClientError::BadCredentials | ClientError::SessionExpired),
+        )) => return Err(Failure::Auth(err.to_string())),
+        Err(err) => return Err(Failure::Error(err.into())),
+    }
+
+    match tree.progress().status().files_failed {
+        0 => Ok(()),
+        failed => Err(Failure::Partial(failed)),
+    }
+}
+
+fn print_changes(set: &ChangeSet) {
+    for change in &set.changes {
+        match change {
+            Change::NewCourse { path, .. } => println!("new course   {path}"),
+            Change::NewFolder { path, .. } => println!("new folder   {path}"),
+            Change::NewFile { path, .. } => println!("new file     {path}"),
+            Change::NewVersion { path, from, to, .. } => {
+                println!("new version  {path} (v{from} -> v{to})")
+            }
+            Change::NewPosts { path, count, .. } => println!("new posts    {path} ({count})"),
+            Change::Removed { path, .. } => println!("removed      {path}"),
+            Change::Renamed { path, from, .. } => println!("renamed      {from} -> {path}"),
+        }
+    }
+}
+
+fn changes(tree: &IliasTree, limit: usize, json: bool) -> CliResult {
+    let sets = tree
+        .recent_changes()
+        .into_iter()
+        .take(limit)
+        .collect::<Vec<_>>();
+    if json {
+        return print_json(&sets);
+    }
+    for set in sets {
+        let synced_at = DateTime::from_timestamp(set.synced_at as i64, 0)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!("sync at {synced_at}");
+        print_changes(&set);
+        println!();
+    }
+    Ok(())
+}
+
+fn ls(tree: &IliasTree, path: Option<String>, json: bool) -> CliResult {
+    let node = resolve(tree, path.as_deref().unwrap_or_default())?;
+    let entries = children(&node).iter().map(Entry::new).collect::<Vec<_>>();
+    if json {
+        return print_json(&entries);
+    }
+    for entry in entries {
+        let path = entry
+            .path
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        let ignored = if entry.ignored { " (ignored)" } else { "" };
+        println!(
+            "{:>6} {:<8} {:<40} {path}{ignored}",
+            entry.id, entry.kind, entry.title
+        );
+    }
+    Ok(())
+}
+
+fn open(tree: &IliasTree, query: &str, json: bool) -> CliResult {
+    let node = resolve(tree, query)?;
+    let path = node
+        .breed
+        .local_path()
+        .filter(|path| path.exists())
+        .ok_or_else(|| anyhow::anyhow!("{} isn't stored locally", node.title))?;
+    open::that(&path)?;
+    if json {
+        print_json(&Entry::new(&node))?;
+    }
+    Ok(())
+}
+
+fn status(tree: &IliasTree, json: bool) -> CliResult {
+    let root = tree.get_root_node();
+    let courses = children(&root);
+    let (mut files, mut downloaded) = (0, 0);
+    let mut queue = courses.clone();
+    while let Some(node) = queue.pop() {
+        if let IlNodeType::File { meta, .. } = &node.breed {
+            files += 1;
+            downloaded += usize::from(meta.is_some());
+        }
+        queue.extend(children(&node));
+    }
+    let status = Status {
+        instance: tree.instance().name.clone(),
+        credentials_cached: Credentials::cached(),
+        courses: courses.len(),
+        files,
+        downloaded,
+        conflicts: tree.conflicts().len(),
+    };
+    if json {
+        return print_json(&status);
+    }
+    println!("instance:    {}", status.instance);
+    println!(
+        "credentials: {}",
+        if status.credentials_cached {
+            "cached"
+        } else {
+            "none"
+        }
+    );
+    println!("courses:     {}", status.courses);
+    println!(
+        "files:       {} ({} downloaded)",
+        status.files, status.downloaded
+    );
+    println!("conflicts:   {}", status.conflicts);
+    Ok(())
+}
+
+async fn run(cli: Cli) -> CliResult {
+    if let Some(dir) = &cli.dir {
+        env::set_current_dir(dir)?;
+    }
+    let tree = IliasTree::new(IliasInstance::load(), Settings::load()).await;
+    match cli.command {
+        Command::Login { username } => login(&tree, username).await,
+        Command::Sync => sync(&tree, cli.json).await,
+        Command::Daemon => {
+            login_cached(&tree).await?;
+            print_progress(&tree, cli.json);
+            Ok(daemon::run(Arc::new(tree)).await?)
+        }
+        Command::Changes { limit } => changes(&tree, limit, cli.json),
+        Command::Tree if cli.json => print_json(&tree.get_root_node()),
+        Command::Tree => {
+            print_tree(&tree.get_root_node(), 0);
+            Ok(())
+        }
+        Command::Ls { path } => ls(&tree, path, cli.json),
+        Command::Download { node, quality } => {
+            let node = resolve(&tree, &node)?;
+            login_cached(&tree).await?;
+            match node.breed {
+                IlNodeType::Video { .. } => tree.download_video(node.id, quality).await?,
+                _ => tree.download(node.id).await?,
+            }
+            Ok(tree.save()?)
+        }
+        Command::Open { node } => open(&tree, &node, cli.json),
+        Command::Status => status(&tree, cli.json),
+        Command::Policy { node, policy } => {
+            let id = resolve(&tree, &node)?.id;
+            let policy = match policy {
+                PolicyArg::DownloadAll => Some(SyncPolicy::DownloadAll),
+                PolicyArg::MetadataOnly => Some(SyncPolicy::MetadataOnly),
+                PolicyArg::Never => Some(SyncPolicy::Never),
+                PolicyArg::Inherit => None,
+            };
+            tree.set_policy(id, policy)?;
+            Ok(tree.save()?)
+        }
+        Command::Deadlines { days } => deadlines(&tree, days, cli.json),
+        Command::Submit { assignment, files } => {
+            let id = resolve(&tree, &assignment)?.id;
+            login_cached(&tree).await?;
+            let submitted = tree.submit(id, &files).await?;
+            tree.save()?;
+            if cli.json {
+                return print_json(&submitted);
+            }
+            for file in submitted {
+                let at = file.submitted_at.unwrap_or_default();
+                println!("{:<50} {at}", file.name);
+            }
+            Ok(())
+        }
+        Command::Calendar { output, course } => {
+            let filter = match course.is_empty() {
+                true => CourseFilter::All,
+                false => CourseFilter::Only(course.into_iter().collect()),
+            };
+            let events = tree.export_calendar(&output, &filter)?;
+            if cli.json {
+                return print_json(&serde_json::json!({ "path": output, "events": events }));
+            }
+            println!("wrote {events} events to {}", output.display());
+            Ok(())
+        }
+        Command::Search { query, limit } => {
+            let hits = tree.search(&query.join(" "), limit);
+            if cli.json {
+                return print_json(&hits);
+            }
+            for hit in hits {
+                println!("{}", hit.title);
+                println!("    {}", hit.location);
+                if let Some(snippet) = hit.snippet {
+                    println!("    {snippet}");
+                }
+            }
+            Ok(())
+        }
+        Command::Threads { forum } => threads(&tree, &forum, cli.json),
+        Command::Thread {
+            forum,
+            thread: query,
+            export,
+        } => thread(&tree, &forum, &query, export, cli.json),
+        Command::Read {
+            forum,
+            thread,
+            unread,
+        } => {
+            let (_, thread) = resolve_thread(&tree, &forum, &thread)?;
+            Ok(tree.mark_read(&thread.uri, None, !unread)?)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+    let json = cli.json;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            if json {
+                let error =
+                    serde_json::json!({ "error": failure.message(), "code": failure.code() });
+                eprintln!("{error}");
+            } else {
+                eprintln!("error: {}", failure.message());
+            }
+            ExitCode::from(failure.code())
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    exercise,
+    ilias::{IlNodeType, WrappedNode},
+    instance::IliasInstance,
+    tree::CourseFilter,
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+/// Longest line in octets before it is folded, see RFC 5545 3.1
+const MAX_LINE: usize = 75;
+
+/// Start and end of a session as ilias lists it: `24. Okt 2026, 10:00 - 12:00: Vorlesung`,
+/// `Heute, 10:00 - 12:00` or `24. Okt 2026, 10:00 - 25. Okt 2026, 12:00`
+pub fn parse_session(
+    text: &str,
+    today: NaiveDate,
+) -> Option<(DateTime<Local>, Option<DateTime<Local>>)> {
+    // times are followed by digits, the title by a space
+    let range = text.split(": ").next()?;
+    let (from, to) = match range.split_once(" - ") {
+        Some((from, to)) => (from, Some(to)),
+        None => (range, None),
+    };
+    let start = exercise::parse_date(from, today)?;
+    let end = to.and_then(|to| {
+        exercise::parse_date(to, start.date()).or_else(|| {
+            let time = NaiveTime::parse_from_str(to.trim(), "%H:%M").ok()?;
+            Some(start.date().and_time(time))
+        })
+    });
+    let local = |date: NaiveDateTime| Local.from_local_datetime(&date).earliest();
+    Some((local(start)?, end.and_then(local)))
+}
+
+struct Event {
+    uid: String,
+    summary: String,
+    start: DateTime<Local>,
+    end: Option<DateTime<Local>>,
+    location: Option<String>,
+    description: String,
+    url: String,
+    category: &'static str,
+}
+
+/// The same for every export of an ilias object, so calendar clients update the event
+/// instead of adding it again. Built from the `ref_id` (and assignment) of the object,
+/// hashed uri for links without one.
+fn uid(uri: &str, instance: &IliasInstance) -> String {
+    let url = Url::parse(&instance.url(uri)).ok();
+    let host = url
+        .as_ref()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "better-ilias".to_string());
+    let ref_id = url.as_ref().and_then(|url| {
+        let (_, id) = url.query_pairs().find(|(key, _)| key == "ref_id")?;
+        Some(id.to_string())
+    });
+    let fragment = url
+        .as_ref()
+        .and_then(|url| url.fragment().map(|fragment| format!("-{fragment}")))
+        .unwrap_or_default();
+    match ref_id {
+        Some(ref_id) => format!("{ref_id}{fragment}@{host}"),
+        None => format!("{}@{host}", &hex::encode(Sha256::digest(uri))[..16]),
+    }
+}
+
+fn collect(
+    node: &WrappedNode,
+    course: Option<&str>,
+    parent: &str,
+    instance: &IliasInstance,
+    events: &mut Vec<Event>,
+) {
+    let node = node.lock().unwrap();
+    match &node.breed {
+        IlNodeType::Assignment { info, .. } => {
+            if let Some(deadline) = info.deadline {
+                events.push(Event {
+                    uid: uid(&node.uri, instance),
+                    summary: format!("{parent}: {}", node.title),
+                    start: deadline,
+                    end: None,
+                    location: None,
+                    description: course.unwrap_or_default().to_string(),
+                    url: instance.url(&node.uri),
+                    category: "Deadline",
+                });
+            }
+        }
+        IlNodeType::Session {
+            start: Some(start),
+            end,
+            location,
+        } => events.push(Event {
+            uid: uid(&node.uri, instance),
+            // the listed title starts with the dates
+            summary: match node.title.split_once(": ") {
+                Some((_, title)) => format!("{}: {title}", course.unwrap_or_default()),
+                None => course.unwrap_or(&node.title).to_string(),
+            },
+            start: *start,
+            end: *end,
+            location: location.clone(),
+            description: course.unwrap_or_default().to_string(),
+            url: instance.url(&node.uri),
+            category: "Session",
+        }),
+        _ => {}
+    }
+    let course = course.or(Some(&node.title));
+    for child in node.children.iter().flatten() {
+        collect(child, course, &node.title, instance, events);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn utc(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Appends `line` folded after [MAX_LINE] octets without splitting characters
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for character in line.chars() {
+        if width + character.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(character);
+        width += character.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// An iCalendar with the deadlines of the assignments and the sessions of the courses
+/// below `root` that match `filter`. Returns it with the number of events.
+pub fn export(
+    root: &WrappedNode,
+    filter: &CourseFilter,
+    instance: &IliasInstance,
+) -> (String, usize) {
+    let mut events = vec![];
+    let courses = root.lock().unwrap().children.clone().unwrap_or_default();
+    for course in courses
+        .iter()
+        .filter(|course| filter.matches(&course.lock().unwrap()))
+    {
+        collect(course, None, "", instance, &mut events);
+    }
+    events.sort_by_key(|event| event.start);
+
+    let mut out = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//better-ilias//calendar//EN",
+        "CALSCALE:GREGORIAN",
+        &format!("X-WR-CALNAME:{}", escape(&instance.name)),
+    ] {
+        push_line(&mut out, line);
+    }
+    let stamp = utc(Local::now());
+    for event in &events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", event.uid));
+        push_line(&mut out, &format!("DTSTAMP:{stamp}"));
+        push_line(&mut out, &format!("DTSTART:{}", utc(event.start)));
+        push_line(
+            &mut out,
+            &format!("DTEND:{}", utc(event.end.unwrap_or(event.start))),
+        );
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&event.summary)));
+        if let Some(location) = &event.location {
+            push_line(&mut out, &format!("LOCATION:{}", escape(location)));
+        }
+        if !event.description.is_empty() {
+            push_line(
+                &mut out,
+                &format!("DESCRIPTION:{}", escape(&event.description)),
+            );
+        }
+        push_line(&mut out, &format!("URL:{}", event.url));
+        push_line(&mut out, &format!("CATEGORIES:{}", event.category));
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    (out, events.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance() -> IliasInstance {
+        IliasInstance {
+            base_url: "https://ilias.example/".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        let date = NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+        Local
+            .from_local_datetime(&date.and_hms_opt(hour, minute, 0).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn uids_stay_the_same_for_an_object() {
+        let instance = instance();
+        assert_eq!(
+            uid("ilias.php?ref_id=123&cmd=showSummary", &instance),
+            "123@ilias.example"
+        );
+        assert_eq!(
+            uid("ilias.php?ref_id=123&cmd=showOverview#ass_42", &instance),
+            "123-ass_42@ilias.example"
+        );
+        let hashed = uid("goto.php?target=sess_9", &instance);
+        assert!(hashed.ends_with("@ilias.example"));
+        assert_eq!(hashed.len(), 16 + "@ilias.example".len());
+        assert_eq!(hashed, uid("goto.php?target=sess_9", &instance));
+        assert_ne!(hashed, uid("goto.php?target=sess_10", &instance));
+    }
+
+    #[test]
+    fn parses_session_titles() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        assert_eq!(
+            parse_session("24. Okt 2026, 10:00 - 12:00: Vorlesung", today),
+            Some((at(24, 10, 0), Some(at(24, 12, 0))))
+        );
+        assert_eq!(
+            parse_session("Heute, 10:15 - 11:45", today),
+            Some((at(18, 10, 15), Some(at(18, 11, 45))))
+        );
+        assert_eq!(
+            parse_session(
+                "24. Okt 2026, 10:00 - 25. Okt 2026, 12:00: Blockseminar",
+                today
+            ),
+            Some((at(24, 10, 0), Some(at(25, 12, 0))))
+        );
+        assert_eq!(
+            parse_session("30. Okt 2026, 14:00: Sprechstunde", today),
+            Some((at(30, 14, 0), None))
+        );
+        assert_eq!(parse_session("Vorbesprechung", today), None);
+    }
+
+    #[test]
+    fn folds_long_lines_without_splitting_characters() {
+        let mut out = String::new();
+        push_line(&mut out, &"a".repeat(80));
+        assert_eq!(out, format!("{}\r\n {}\r\n", "a".repeat(75), "a".repeat(5)));
+
+        let mut out = String::new();
+        let line = format!("SUMMARY:{}", "ü".repeat(60));
+        push_line(&mut out, &line);
+        for folded in out.split("\r\n").filter(|line| !line.is_empty()) {
+            assert!(folded.len() <= MAX_LINE);
+        }
+        assert_eq!(out.replace("\r\n ", ""), format!("{line}\r\n"));
+    }
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(
+            escape("Raum 1, Geb. 2; Eingang\\Nord\nhinten"),
+            r"Raum 1\, Geb. 2\; Eingang\\Nord\nhinten"
+        );
+    }
+}
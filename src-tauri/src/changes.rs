@@ -0,0 +1,357 @@
+use crate::{
+    forum::ForumStore,
+    ilias::{IlNodeType, WrappedNode},
+};
+use anyhow::{anyhow, Result};
+use dirs::cache_dir;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many change sets are kept in `changes.json`
+const KEEP_SETS: usize = 50;
+
+/// Something a sync changed, `path` are the titles from the course down to the node
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Change {
+    NewCourse {
+        uri: String,
+        path: String,
+    },
+    NewFolder {
+        uri: String,
+        path: String,
+    },
+    NewFile {
+        uri: String,
+        path: String,
+    },
+    NewVersion {
+        uri: String,
+        path: String,
+        from: usize,
+        to: usize,
+    },
+    /// `count` posts appeared in the thread at `uri`, which may be new itself
+    NewPosts {
+        uri: String,
+        path: String,
+        count: usize,
+    },
+    Removed {
+        uri: String,
+        path: String,
+    },
+    Renamed {
+        uri: String,
+        path: String,
+        from: String,
+    },
+}
+
+/// Everything one sync changed
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ChangeSet {
+    /// Unix timestamp in seconds
+    pub synced_at: u64,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Course,
+    Folder,
+    File,
+    Thread,
+    Other,
+}
+
+struct Entry {
+    title: String,
+    path: String,
+    kind: Kind,
+    version: Option<usize>,
+    posts: usize,
+}
+
+/// The nodes of a tree and the threads of its forums by uri, taken before and after a sync
+/// to compare them
+pub struct Snapshot(HashMap<String, Entry>);
+
+pub fn snapshot(root: &WrappedNode, forums: &ForumStore) -> Snapshot {
+    fn visit(
+        node: &WrappedNode,
+        parent: Option<&str>,
+        forums: &ForumStore,
+        entries: &mut HashMap<String, Entry>,
+    ) {
+        let node = node.lock().unwrap();
+        let path = match parent {
+            Some(parent) if !parent.is_empty() => format!("{parent}/{}", node.title),
+            Some(_) => node.title.clone(),
+            None => String::new(),
+        };
+        let kind = match &node.breed {
+            IlNodeType::Folder { .. } if parent == Some("") => Kind::Course,
+            IlNodeType::Folder { .. } => Kind::Folder,
+            IlNodeType::File { .. } => Kind::File,
+            _ => Kind::Other,
+        };
+        for child in node.children.iter().flatten() {
+            visit(child, Some(&path), forums, entries);
+        }
+        if node.breed == IlNodeType::Forum {
+            for thread in forums.threads(&node.uri) {
+                entries.insert(
+                    thread.uri.clone(),
+                    Entry {
+                        title: thread.title.clone(),
+                        path: format!("{path}/{}", thread.title),
+                        kind: Kind::Thread,
+                        version: None,
+                        posts: thread.posts.len(),
+                    },
+                );
+            }
+        }
+        if parent.is_some() {
+            let version = match &node.breed {
+                IlNodeType::File { version, .. } => Some(*version),
+                _ => None,
+            };
+            entries.insert(
+                node.uri.clone(),
+                Entry {
+                    title: node.title.clone(),
+                    path,
+                    kind,
+                    version,
+                    posts: 0,
+                },
+            );
+        }
+    }
+    let mut entries = HashMap::new();
+    visit(root, None, forums, &mut entries);
+    Snapshot(entries)
+}
+
+impl Snapshot {
+    /// What changed between `self` and the later snapshot `after`
+    pub fn diff(&self, after: &Snapshot) -> ChangeSet {
+        let mut changes = vec![];
+        for (uri, new) in &after.0 {
+            let uri = uri.clone();
+            let path = new.path.clone();
+            match self.0.get(&uri) {
+                None => changes.push(match new.kind {
+                    Kind::Course => Change::NewCourse { uri, path },
+                    Kind::Folder => Change::NewFolder { uri, path },
+                    Kind::File => Change::NewFile { uri, path },
+                    Kind::Thread => Change::NewPosts {
+                        uri,
+                        path,
+                        count: new.posts,
+                    },
+                    Kind::Other => continue,
+                }),
+                Some(old) => {
+                    // nodes below a renamed folder only moved along with it
+                    if old.title != new.title {
+                        changes.push(Change::Renamed {
+                            uri: uri.clone(),
+                            path: path.clone(),
+                            from: old.path.clone(),
+                        });
+                    }
+                    if new.posts > old.posts {
+                        changes.push(Change::NewPosts {
+                            uri: uri.clone(),
+                            path: path.clone(),
+                            count: new.posts - old.posts,
+                        });
+                    }
+                    if let (Some(from), Some(to)) = (old.version, new.version) {
+                        if from != to {
+                            changes.push(Change::NewVersion {
+                                uri,
+                                path,
+                                from,
+                                to,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        for (uri, old) in &self.0 {
+            if old.kind != Kind::Other && !after.0.contains_key(uri) {
+                changes.push(Change::Removed {
+                    uri: uri.clone(),
+                    path: old.path.clone(),
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.path().cmp(b.path()));
+        ChangeSet {
+            synced_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            changes,
+        }
+    }
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::NewCourse { path, .. }
+            | Change::NewFolder { path, .. }
+            | Change::NewFile { path, .. }
+            | Change::NewVersion { path, .. }
+            | Change::NewPosts { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Renamed { path, .. } => path,
+        }
+    }
+}
+
+fn changes_path() -> Option<PathBuf> {
+    cache_dir().map(|mut path| {
+        path.push("better-ilias/changes.json");
+        path
+    })
+}
+
+/// The change sets of earlier syncs, newest first
+pub fn load() -> Vec<ChangeSet> {
+    let Some(path) = changes_path().filter(|path| path.exists()) else {
+        return vec![];
+    };
+    match fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| Ok(serde_json::from_str(&data)?))
+    {
+        Ok(sets) => sets,
+        Err(err) => {
+            warn!("can't load changes from {path:?}: {err}");
+            vec![]
+        }
+    }
+}
+
+/// Puts `set` in front of `sets` unless it is empty and writes them to `changes.json`
+pub fn record(sets: &mut Vec<ChangeSet>, set: ChangeSet) -> Result<()> {
+    if set.changes.is_empty() {
+        return Ok(());
+    }
+    sets.insert(0, set);
+    sets.truncate(KEEP_SETS);
+    let path = changes_path().ok_or(anyhow!("can't create path"))?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, serde_json::to_string(sets)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ilias::IlNode,
+        stub::{self, Reply},
+        tree::{self, CourseFilter},
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// The listing the stub serves: the title of the course and its folders
+    type Listing = (&'static str, Vec<(&'static str, &'static str)>);
+
+    /// Syncs `root` against the stub serving `listing` and returns what changed
+    async fn sync(root: &WrappedNode, listing: &Arc<Mutex<Listing>>) -> Vec<Change> {
+        let served = listing.clone();
+        let (client, _) = stub::serve(move |line| {
+            let (course, folders) = served.lock().unwrap().clone();
+            if line.contains("ilmembershipoverviewgui") {
+                Reply::Page(stub::courses(&[("goto.php?target=crs_1", course)]))
+            } else if line.contains("crs_1") {
+                let items = folders
+                    .iter()
+                    .map(|(uri, title)| ("fold", *uri, *title))
+                    .collect::<Vec<_>>();
+                Reply::Page(stub::items(&items))
+            } else {
+                Reply::Page(String::new())
+            }
+        })
+        .await;
+        let forums = ForumStore::default();
+        let before = snapshot(root, &forums);
+        tree::update_root(stub::context(client), root.clone(), CourseFilter::All)
+            .await
+            .unwrap()
+            .unwrap();
+        before.diff(&snapshot(root, &forums)).changes
+    }
+
+    fn new_folder(uri: &str, path: &str) -> Change {
+        Change::NewFolder {
+            uri: uri.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    fn renamed(uri: &str, path: &str, from: &str) -> Change {
+        Change::Renamed {
+            uri: uri.to_string(),
+            path: path.to_string(),
+            from: from.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_what_a_sync_changed() {
+        let course = "goto.php?target=crs_1";
+        let root = Arc::new(Mutex::new(IlNode::default()));
+        let listing = Arc::new(Mutex::new((
+            "Kurs",
+            vec![("fold_a", "Folien"), ("fold_b", "Alt")],
+        )));
+        assert_eq!(
+            sync(&root, &listing).await,
+            [
+                Change::NewCourse {
+                    uri: course.to_string(),
+                    path: "Kurs".to_string(),
+                },
+                new_folder("fold_b", "Kurs/Alt"),
+                new_folder("fold_a", "Kurs/Folien"),
+            ]
+        );
+        assert_eq!(sync(&root, &listing).await, []);
+
+        *listing.lock().unwrap() = (
+            "Analysis I",
+            vec![("fold_a", "Vorlesung"), ("fold_c", "Blatt")],
+        );
+        // nodes below the renamed course only moved along with it
+        assert_eq!(
+            sync(&root, &listing).await,
+            [
+                renamed(course, "Analysis I", "Kurs"),
+                new_folder("fold_c", "Analysis I/Blatt"),
+                renamed("fold_a", "Analysis I/Vorlesung", "Kurs/Folien"),
+                Change::Removed {
+                    uri: "fold_b".to_string(),
+                    path: "Kurs/Alt".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -1,29 +1,42 @@
-use crate::ilias::IlNode;
+use crate::download_queue::Throttle;
+use crate::file_download::{discard_part, download_to, resume_offset, with_range};
+use crate::file_type::{sniff_extension, with_extension, ResponseName};
+use crate::ilias::{FileMeta, IlNode, IlNodeType};
+use crate::instance::{IliasInstance, LoginStrategy};
+use crate::progress::Progress;
+use crate::settings::{DownloadSettings, Quality};
 use crate::string_serializer;
+use crate::submission::{self, SubmittedFile, Upload};
+use crate::video::{self, VideoInfo, VideoPage, VideoSource};
 use anyhow::{anyhow, Context, Result};
 use dirs::config_dir;
 use headless_chrome::Browser;
 use lazy_static::lazy_static;
 use log::{info, warn};
-use reqwest::{redirect::Policy, Client, ClientBuilder, Method, StatusCode};
-use scraper::{Html, Selector};
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    header::{COOKIE, LOCATION},
+    multipart::{Form, Part},
+    redirect::Policy,
+    Client, ClientBuilder, RequestBuilder, Response, StatusCode, Url,
+};
+use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use std::{
     fs::{self},
     io,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::Utf8Error,
     sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
-use tokio::fs::create_dir_all;
+use tokio::sync::{Mutex as AsyncMutex, RwLock};
 
 #[derive(Debug, Error, Serialize)]
 pub enum ClientError {
     #[error("Client has no token")]
     NoToken,
-    #[error("Requested file didn't answer with content-type")]
-    NoContentType,
     #[error("Parse Error")]
     #[serde(with = "string_serializer")]
     Parser(#[from] Utf8Error),
@@ -32,6 +45,10 @@ pub enum ClientError {
     Reqwest(#[from] reqwest::Error),
     #[error("Shiat da scheinen die Logindaten nicht zu stimmen :/ uwu")]
     BadCredentials,
+    #[error("Session expired and logging in again didn't help")]
+    SessionExpired,
+    #[error("Ilias answered with status {0}")]
+    Status(u16),
     #[error(transparent)]
     #[serde(with = "string_serializer")]
     Anyhow(#[from] anyhow::Error),
@@ -40,11 +57,42 @@ pub enum ClientError {
 lazy_static! {
     pub static ref CONTEXT: Selector = Selector::parse("#LoginForm_context").unwrap();
     pub static ref INPUTS: Selector = Selector::parse("input").unwrap();
+    pub static ref FORM: Selector = Selector::parse("form").unwrap();
+    pub static ref SAML_RESPONSE: Selector =
+        Selector::parse("input[name=\"SAMLResponse\"]").unwrap();
+    // login forms of ilias itself and of the IdP
+    pub static ref LOGIN_FORM: Selector =
+        Selector::parse("form[name=\"formlogin\"], #il_prop_cont_username, #LoginForm_username")
+            .unwrap();
 }
 
-pub struct IliasClient {
+/// How often the shibboleth flow follows intermediate forms (consent, local-storage checks, ...)
+/// before giving up on finding the SAMLResponse
+const MAX_SAML_STEPS: usize = 5;
+
+/// How many confirmation pages an upload may ask to get through
+const MAX_CONFIRMATIONS: usize = 3;
+
+/// The current `PHPSESSID` and how often it was renewed
+#[derive(Clone)]
+struct Session {
     token: String,
+    generation: usize,
+}
+
+impl Session {
+    fn cookie(&self) -> String {
+        "PHPSESSID=".to_owned() + &self.token
+    }
+}
+
+pub struct IliasClient {
+    session: RwLock<Session>,
+    /// Held while logging in again so that only one task renews an expired session
+    relogin: AsyncMutex<()>,
+    creds: Credentials,
     client: Client,
+    instance: Arc<IliasInstance>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -53,6 +101,17 @@ pub struct Credentials {
     pw: String,
 }
 
+impl Credentials {
+    pub fn new(name: String, pw: String) -> Self {
+        Self { name, pw }
+    }
+
+    /// Whether credentials from an earlier login are stored
+    pub fn cached() -> bool {
+        creds_path().is_some_and(|path| path.exists())
+    }
+}
+
 fn creds_path() -> Option<PathBuf> {
     config_dir().map(|mut path| {
         path.push("better-ilias/credentials.json");
@@ -78,28 +137,216 @@ fn save_creds(creds: &Credentials) -> Result<()> {
     Ok(())
 }
 
+/// Resolves the action of a form relative to the page it was found on
+fn form_action(form: &ElementRef, page: &Url) -> Result<Url, ClientError> {
+    let action = form.value().attr("action").unwrap_or_default();
+    Ok(page
+        .join(action)
+        .with_context(|| format!("invalid form action {action}"))?)
+}
+
+/// Collects all named inputs of a form with their preset values
+fn form_fields(form: &ElementRef) -> Vec<(String, String)> {
+    form.select(&INPUTS)
+        .filter_map(|input| {
+            let name = input.value().attr("name")?;
+            let value = input.value().attr("value").unwrap_or_default();
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parses a page unless it is a login form
+fn parse_page(text: &str) -> Option<Html> {
+    let html = Html::parse_document(text);
+    if html.select(&LOGIN_FORM).next().is_some() {
+        None
+    } else {
+        Some(html)
+    }
+}
+
+fn session_cookie(jar: &Jar, url: &Url) -> Option<String> {
+    let cookies = jar.cookies(url)?;
+    cookies
+        .to_str()
+        .ok()?
+        .split(';')
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == "PHPSESSID")
+        .map(|(_, value)| value.to_string())
+}
+
+/// Streams `resp` to `path`. If the headers didn't tell the type of the file (`sniff`),
+/// it is renamed after its first bytes. Returns the final path.
+async fn write_file(
+    resp: Response,
+    uri: &str,
+    path: PathBuf,
+    offset: u64,
+    sniff: bool,
+    throttle: Option<&Throttle>,
+    progress: &Progress,
+) -> Result<(PathBuf, FileMeta)> {
+    info!("Downloading file {:?}", path);
+    let mut on_bytes = |bytes, total| progress.file_bytes(uri, &path, bytes, total);
+    let downloaded = download_to(resp, &path, offset, throttle, &mut on_bytes).await?;
+    let meta = FileMeta {
+        sha256: downloaded.sha256,
+        size: downloaded.size,
+        downloaded_at: unix_now(),
+    };
+
+    if sniff {
+        if let Some(sniffed) = sniff_extension(&path)? {
+            let sniffed_path = with_extension(&path, sniffed);
+            tokio::fs::rename(&path, &sniffed_path).await?;
+            return Ok((sniffed_path, meta));
+        }
+    }
+    Ok((path, meta))
+}
+
 impl IliasClient {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(instance: Arc<IliasInstance>) -> Result<Self> {
         let creds = load_creds()?;
-        let token = Self::acquire_token(&creds).await?;
-        Ok(IliasClient {
-            token,
-            client: Client::new(),
-        })
+        Ok(Self::with_creds(instance, creds).await?)
     }
 
-    pub async fn with_creds(creds: Credentials) -> Result<Self, ClientError> {
-        let token = Self::acquire_token(&creds).await?;
+    pub async fn with_creds(
+        instance: Arc<IliasInstance>,
+        creds: Credentials,
+    ) -> Result<Self, ClientError> {
+        let token = Self::acquire_token(&instance, &creds).await?;
         Ok(IliasClient {
-            token,
+            session: RwLock::new(Session {
+                token,
+                generation: 0,
+            }),
+            relogin: AsyncMutex::new(()),
+            creds,
             client: Client::new(),
+            instance,
         })
     }
 
-    pub async fn acquire_token(creds: &Credentials) -> Result<String, ClientError> {
+    pub fn instance(&self) -> &IliasInstance {
+        &self.instance
+    }
+
+    /// Logs in with the given credentials and returns the `PHPSESSID` of the new session.
+    ///
+    /// With [LoginStrategy::Shibboleth] the plain http flow is tried first and a headless browser
+    /// is only started if that flow fails for reasons other than wrong credentials.
+    pub async fn acquire_token(
+        instance: &IliasInstance,
+        creds: &Credentials,
+    ) -> Result<String, ClientError> {
+        let token = match instance.login_strategy {
+            LoginStrategy::Shibboleth => {
+                match Self::acquire_token_shibboleth(instance, creds).await {
+                    Ok(token) => token,
+                    Err(ClientError::BadCredentials) => return Err(ClientError::BadCredentials),
+                    Err(err) => {
+                        warn!("shibboleth login failed, falling back to browser: {err}");
+                        Self::acquire_token_browser(instance, creds).await?
+                    }
+                }
+            }
+            LoginStrategy::Browser => Self::acquire_token_browser(instance, creds).await?,
+        };
+
+        if let Err(err) = save_creds(creds) {
+            warn!("couldn't save credentials because of: {err}")
+        }
+        Ok(token)
+    }
+
+    /// Walks through the SAML redirects with a cookie-storing client:
+    /// login page of the IdP -> credentials -> SAMLResponse -> ilias
+    pub async fn acquire_token_shibboleth(
+        instance: &IliasInstance,
+        creds: &Credentials,
+    ) -> Result<String, ClientError> {
+        let jar = Arc::new(Jar::default());
+        let client = ClientBuilder::new().cookie_provider(jar.clone()).build()?;
+
+        // ilias redirects to the login page of the IdP
+        let resp = client.get(&instance.login_url).send().await?;
+        let url = resp.url().clone();
+        let (action, fields) = {
+            let html = Html::parse_document(&resp.text().await?);
+            let form = html
+                .select(&FORM)
+                .find(|form| form.select(&CONTEXT).next().is_some())
+                .ok_or(anyhow!("no login form found on {url}"))?;
+            let fields = form
+                .select(&INPUTS)
+                .filter_map(|input| {
+                    let name = input.value().attr("name")?.to_string();
+                    let value = match input.value().id() {
+                        Some("LoginForm_username") => creds.name.clone(),
+                        Some("LoginForm_password") => creds.pw.clone(),
+                        _ => input.value().attr("value").unwrap_or_default().to_string(),
+                    };
+                    Some((name, value))
+                })
+                .collect::<Vec<_>>();
+            (form_action(&form, &url)?, fields)
+        };
+
+        let mut resp = client.post(action).form(&fields).send().await?;
+        for _ in 0..MAX_SAML_STEPS {
+            let url = resp.url().clone();
+            let (action, fields, is_saml) = {
+                let html = Html::parse_document(&resp.text().await?);
+                if html.select(&CONTEXT).next().is_some() {
+                    // we got the login form again
+                    return Err(ClientError::BadCredentials);
+                }
+
+                let saml_form = html
+                    .select(&FORM)
+                    .find(|form| form.select(&SAML_RESPONSE).next().is_some());
+                let form = match saml_form {
+                    Some(form) => form,
+                    None => html
+                        .select(&FORM)
+                        .next()
+                        .ok_or(anyhow!("IdP answered without SAMLResponse on {url}"))?,
+                };
+                (
+                    form_action(&form, &url)?,
+                    form_fields(&form),
+                    saml_form.is_some(),
+                )
+            };
+            resp = client.post(action).form(&fields).send().await?;
+
+            if is_saml {
+                let ilias_url = Url::parse(&instance.base_url).context("invalid ilias url")?;
+                return session_cookie(&jar, &ilias_url)
+                    .ok_or(anyhow!("No cookie PHPSESSID").into());
+            }
+        }
+        Err(anyhow!("gave up following the IdP after {MAX_SAML_STEPS} forms").into())
+    }
+
+    /// Logs in by remote controlling a headless chrome
+    pub async fn acquire_token_browser(
+        instance: &IliasInstance,
+        creds: &Credentials,
+    ) -> Result<String, ClientError> {
         let browser = Browser::default()?;
         let tab = browser.new_tab()?;
-        tab.navigate_to("https://ilias.uni-freiburg.de/shib_login.php?target=")?;
+        tab.navigate_to(&instance.login_url)?;
 
         tab.wait_for_element("input#LoginForm_username")?.click()?;
         tab.type_str(&creds.name)?;
@@ -108,24 +355,99 @@ impl IliasClient {
         tab.type_str(&creds.pw)?.press_key("Enter")?;
         // This waits so long, maybe it is optimizable
         match tab.wait_for_element("#headerimage") {
-            Ok(_) => {
-                let token = tab
-                    .get_cookies()?
-                    .iter()
-                    .find(|elem| elem.name == "PHPSESSID")
-                    .ok_or(anyhow!("No cookie PHPSESSID"))?
-                    .value
-                    .clone();
-
-                if let Err(err) = save_creds(creds) {
-                    warn!("couldn't save credentials because of: {err}")
-                }
-                Ok(token)
-            }
+            Ok(_) => Ok(tab
+                .get_cookies()?
+                .iter()
+                .find(|elem| elem.name == "PHPSESSID")
+                .ok_or(anyhow!("No cookie PHPSESSID"))?
+                .value
+                .clone()),
             Err(_) => Err(ClientError::BadCredentials),
         }
     }
 
+    /// Logs in again unless another task already did since `stale` was handed out
+    async fn renew_session(&self, stale: &Session) -> Result<(), ClientError> {
+        let _guard = self.relogin.lock().await;
+        if self.session.read().await.generation != stale.generation {
+            return Ok(());
+        }
+        info!("session expired, logging in again");
+        let token = Self::acquire_token(&self.instance, &self.creds).await?;
+        let mut session = self.session.write().await;
+        session.token = token;
+        session.generation += 1;
+        Ok(())
+    }
+
+    fn is_login_url(&self, url: &str) -> bool {
+        let login_url = self
+            .instance
+            .login_url
+            .split('?')
+            .next()
+            .unwrap_or_default();
+        url.starts_with(login_url)
+            || url
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .ends_with("login.php")
+    }
+
+    /// Ilias answers requests of expired sessions with a redirect to the login page
+    fn session_expired(&self, resp: &Response) -> bool {
+        let redirect = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok());
+        self.is_login_url(resp.url().as_str())
+            || redirect.is_some_and(|location| self.is_login_url(location))
+    }
+
+    /// Sends a request with the current session.
+    /// If the session turns out to be expired, logs in once and retries the request.
+    async fn send(
+        &self,
+        client: &Client,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<Response, ClientError> {
+        Ok(self.send_as(client, request).await?.0)
+    }
+
+    /// [send](Self::send) returning the session the response was got with.
+    /// A 401 or 403 may mean an expired session as well, but ilias also sends it for items
+    /// the user may not see. If it persists after logging in again, the status is returned.
+    async fn send_as(
+        &self,
+        client: &Client,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<(Response, Session), ClientError> {
+        let mut renewed = false;
+        loop {
+            let session = self.session.read().await.clone();
+            let resp = request(client)
+                .header(COOKIE, session.cookie())
+                .send()
+                .await?;
+            let denied = matches!(
+                resp.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            );
+            if !denied && !self.session_expired(&resp) {
+                return Ok((resp, session));
+            }
+            if renewed {
+                return Err(match denied {
+                    true => ClientError::Status(resp.status().as_u16()),
+                    false => ClientError::SessionExpired,
+                });
+            }
+            self.renew_session(&session).await?;
+            renewed = true;
+        }
+    }
+
     /// Takes a ilias-link (which is a redirect) and replaces it with the correct location
     pub async fn flatten_link(&self, node: &Arc<Mutex<IlNode>>) -> Result<(), ClientError> {
         let client = ClientBuilder::new()
@@ -135,13 +457,8 @@ impl IliasClient {
             .build()?;
 
         // request to get context and auth-url
-        let uri = node.lock().unwrap().uri.clone();
-
-        let preflight_req = client
-            .get("https://ilias.uni-freiburg.de/".to_string() + &uri)
-            .header("cookie", "PHPSESSID=".to_owned() + &self.token);
-
-        let resp = preflight_req.send().await?;
+        let url = self.instance.url(&node.lock().unwrap().uri);
+        let resp = self.send(&client, |client| client.get(&url)).await?;
 
         let link_location = resp
             .headers()
@@ -155,66 +472,346 @@ impl IliasClient {
     }
 
     pub async fn get_page(&self, uri: &str) -> Result<Html, ClientError> {
-        let req = self
-            .client
-            .request(
-                Method::GET,
-                "https://ilias.uni-freiburg.de/".to_owned() + uri,
-            )
-            .header("cookie", "PHPSESSID=".to_owned() + &*self.token)
-            .build()
-            .context("can't build request")?;
+        let url = self.instance.url(uri);
+        let mut renewed = false;
+        loop {
+            // the session `send` ended up with, it may have logged in again
+            let (resp, session) = self
+                .send_as(&self.client, |client| client.get(&url))
+                .await?;
+            if resp.status() != StatusCode::OK {
+                return Err(ClientError::Status(resp.status().as_u16()));
+            }
 
-        let resp = self.client.execute(req).await?;
-        if resp.status() != StatusCode::OK {
-            return Err(ClientError::NoToken);
+            // some installations answer expired sessions with the login form directly
+            if let Some(html) = parse_page(&resp.text().await?) {
+                return Ok(html);
+            }
+            if renewed {
+                return Err(ClientError::SessionExpired);
+            }
+            self.renew_session(&session).await?;
+            renewed = true;
         }
-        Ok(Html::parse_document(&resp.text().await?))
     }
 
-    pub async fn download_file(&self, file_node: Arc<Mutex<IlNode>>) -> Result<()> {
-        let req = {
-            let node = file_node.lock().unwrap();
-            self.client
-                .request(Method::GET, &node.uri)
-                .header("cookie", "PHPSESSID=".to_owned() + &self.token)
-                .build()?
+    /// Requests `uri`, continuing where an interrupted download to `path` stopped
+    async fn request_file(&self, uri: &str, path: &Path) -> Result<(Response, u64)> {
+        let url = self.instance.url(uri);
+        let offset = resume_offset(path).await;
+        let resp = if self.instance.is_own(&url) {
+            self.send(&self.client, |client| with_range(client.get(&url), offset))
+                .await?
+        } else {
+            // media servers don't get the ilias session
+            with_range(self.client.get(&url), offset).send().await?
         };
-        let resp = self.client.execute(req).await?;
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            discard_part(path).await;
+        }
+        if !resp.status().is_success() {
+            return Err(ClientError::Status(resp.status().as_u16()).into());
+        }
+        Ok((resp, offset))
+    }
 
+    /// Downloads the file of `file_node` unless it exists already. With `limits`, files the
+    /// settings don't allow are marked as only available remotely instead, without reading the body.
+    pub async fn download_file(
+        &self,
+        file_node: Arc<Mutex<IlNode>>,
+        limits: Option<&DownloadSettings>,
+        throttle: Option<&Throttle>,
+        progress: &Progress,
+    ) -> Result<()> {
+        let (uri, known_path, known_size) = {
+            let mut node = file_node.lock().unwrap();
+            let size = node.breed.size();
+            (
+                node.uri.clone(),
+                node.breed.path_mut().unwrap().clone(),
+                size,
+            )
+        };
+        let known_extension = known_path
+            .extension()
+            .and_then(|extension| extension.to_str());
+        if limits.is_some_and(|limits| !limits.allows(known_extension, known_size)) {
+            info!("{known_path:?} is only available remotely");
+            file_node.lock().unwrap().breed.exceed_limits();
+            return Ok(());
+        }
+        let (resp, offset) = self.request_file(&uri, &known_path).await?;
+
+        let name = ResponseName::from_headers(resp.headers());
+        let size = resp.content_length().map(|length| length + offset);
         let path = {
             let mut node = file_node.lock().unwrap();
-            let path = node.breed.path_mut().unwrap();
-            let extension = resp
-                .headers()
-                .get("content-type")
-                .ok_or(ClientError::NoContentType)?
-                .to_str()?
-                .split('/')
-                .nth(1)
-                .unwrap();
-
-            path.set_extension::<&str>(extension);
-            path.clone()
+            if let IlNodeType::File {
+                path,
+                original_name,
+                size: known_size,
+                ..
+            } = &mut node.breed
+            {
+                // a file downloaded before keeps its name
+                if !path.exists() {
+                    *path = name.apply(path);
+                }
+                *original_name = name.original_name.clone();
+                *known_size = size.or(*known_size);
+            }
+            node.breed.path_mut().unwrap().clone()
         };
 
         if path.exists() {
             return Ok(());
         }
 
-        let extension = path.extension().unwrap();
-        if extension == "mp4" || extension == "zip" {
-            *file_node.lock().unwrap().breed.get_local().unwrap() = false;
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if limits.is_some_and(|limits| !limits.allows(extension, size)) {
+            info!("{path:?} is only available remotely");
+            file_node.lock().unwrap().breed.exceed_limits();
+            return Ok(());
         }
 
-        if *file_node.lock().unwrap().breed.get_local().unwrap() {
-            create_dir_all(path.parent().unwrap())
-                .await
-                .unwrap_or_else(|_| panic!("{:?}", path));
-            info!("Downloading file {:?}", path);
-            fs::write(path, resp.text().await?)?;
+        let sniff = name.extension.is_none();
+        let (path, meta) = write_file(resp, &uri, path, offset, sniff, throttle, progress).await?;
+        if let IlNodeType::File {
+            path: known_path,
+            local,
+            meta: known_meta,
+            over_limit,
+            ..
+        } = &mut file_node.lock().unwrap().breed
+        {
+            *local = true;
+            *over_limit = false;
+            *known_path = path;
+            *known_meta = Some(meta);
         }
+        Ok(())
+    }
+
+    /// Resolves the video page at `uri` to the recording's metadata and its sources
+    pub async fn video_sources(&self, uri: &str) -> Result<(VideoInfo, Vec<VideoSource>)> {
+        let page = {
+            let html = self.get_page(uri).await?;
+            video::parse_page(&html, &self.instance.url(uri))
+        };
+        match page.ok_or(anyhow!("no video found on {uri}"))? {
+            VideoPage::Direct(sources) => {
+                let info = VideoInfo {
+                    qualities: video::qualities(&sources),
+                    ..Default::default()
+                };
+                Ok((info, sources))
+            }
+            VideoPage::Opencast { episode_url } => {
+                let resp = self.client.get(&episode_url).send().await?;
+                if !resp.status().is_success() {
+                    return Err(ClientError::Status(resp.status().as_u16()).into());
+                }
+                let episode = serde_json::from_str(&resp.text().await?)?;
+                video::parse_episode(&episode).ok_or(anyhow!("no recording in {episode_url}"))
+            }
+        }
+    }
+
+    /// Downloads the recording of the video node in `quality` and records its metadata.
+    /// Recordings exceeding `limits` aren't downloaded.
+    pub async fn download_video(
+        &self,
+        video_node: Arc<Mutex<IlNode>>,
+        quality: Quality,
+        limits: Option<&DownloadSettings>,
+        throttle: Option<&Throttle>,
+        progress: &Progress,
+    ) -> Result<()> {
+        let (uri, path) = {
+            let node = video_node.lock().unwrap();
+            match &node.breed {
+                IlNodeType::Video { path, .. } => (node.uri.clone(), path.clone()),
+                _ => return Err(anyhow!("{} is not a video", node.uri)),
+            }
+        };
+        let (info, sources) = self.video_sources(&uri).await?;
+        let source = video::pick(&sources, quality).ok_or(anyhow!("no mp4 source for {uri}"))?;
 
+        let path = with_extension(&path, "mp4");
+        let (resp, offset) = self.request_file(&source.url, &path).await?;
+        let size = resp.content_length().map(|length| length + offset);
+        if limits.is_some_and(|limits| !limits.allows(Some("mp4"), size)) {
+            info!("{path:?} is only available remotely");
+            if let IlNodeType::Video {
+                info: known_info, ..
+            } = &mut video_node.lock().unwrap().breed
+            {
+                *known_info = Some(info);
+            }
+            return Ok(());
+        }
+        let (path, meta) = write_file(resp, &uri, path, offset, false, throttle, progress).await?;
+        if let IlNodeType::Video {
+            path: known_path,
+            meta: known_meta,
+            info: known_info,
+        } = &mut video_node.lock().unwrap().breed
+        {
+            *known_path = path;
+            *known_meta = Some(meta);
+            *known_info = Some(info);
+        }
         Ok(())
     }
+
+    /// Downloads the file at `uri` to `path` without touching its node.
+    /// Returns where the file ended up and its fingerprint.
+    pub async fn download_copy(
+        &self,
+        uri: &str,
+        path: &Path,
+        throttle: Option<&Throttle>,
+        progress: &Progress,
+    ) -> Result<(PathBuf, FileMeta)> {
+        let (resp, offset) = self.request_file(uri, path).await?;
+        let name = ResponseName::from_headers(resp.headers());
+        let path = name.apply_extension(path);
+        let sniff = name.extension.is_none();
+        write_file(resp, uri, path, offset, sniff, throttle, progress).await
+    }
+
+    /// Hands in `files` on the submission page `submit_uri` of an assignment.
+    /// Returns the files ilias lists as submitted afterwards.
+    pub async fn submit_files(
+        &self,
+        submit_uri: &str,
+        files: &[PathBuf],
+    ) -> Result<Vec<SubmittedFile>> {
+        let mut parts = vec![];
+        for path in files {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(anyhow!("{path:?} is not a file"))?;
+            let data = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("can't read {path:?}"))?;
+            parts.push((name.to_string(), data));
+        }
+
+        let page = Url::parse(&self.instance.url(submit_uri)).context("invalid submission page")?;
+        let upload = submission::upload(&self.get_page(page.as_str()).await?, &page);
+        let form = match upload {
+            Upload::Form(form) => form,
+            Upload::Link(link) => {
+                submission::upload_form(&self.get_page(link.as_str()).await?, &link)
+                    .ok_or(anyhow!("no upload form on {link}"))?
+            }
+            Upload::Closed(notice) => {
+                return Err(anyhow!(
+                    "ilias doesn't accept submissions for this assignment: {}",
+                    notice.as_deref().unwrap_or("no upload form")
+                ))
+            }
+        };
+
+        info!("uploading {} files to {}", parts.len(), form.action);
+        let mut resp = self
+            .send(&self.client, |client| {
+                let mut multipart = Form::new();
+                for (name, value) in &form.fields {
+                    multipart = multipart.text(name.clone(), value.clone());
+                }
+                for (name, data) in &parts {
+                    let part = Part::bytes(data.clone()).file_name(name.clone());
+                    multipart = multipart.part(form.file_field.clone(), part);
+                }
+                client.post(form.action.clone()).multipart(multipart)
+            })
+            .await?;
+        for _ in 0..MAX_CONFIRMATIONS {
+            if !resp.status().is_success() {
+                return Err(ClientError::Status(resp.status().as_u16()).into());
+            }
+            let url = resp.url().clone();
+            let confirmation = {
+                let html = Html::parse_document(&resp.text().await?);
+                if let Some(error) = submission::error(&html) {
+                    return Err(anyhow!("ilias refused the upload: {error}"));
+                }
+                submission::confirmation(&html, &url)
+            };
+            let Some((action, fields)) = confirmation else {
+                break;
+            };
+            resp = self
+                .send(&self.client, |client| {
+                    client.post(action.clone()).form(&fields)
+                })
+                .await?;
+        }
+
+        // ilias redirects back to the submission page, which lists what was handed in
+        Ok(submission::submitted_files(
+            &self.get_page(page.as_str()).await?,
+        ))
+    }
+
+    /// A client with the session `token` that never logs in by itself
+    #[cfg(test)]
+    pub(crate) fn with_session(instance: IliasInstance, token: &str) -> Self {
+        IliasClient {
+            session: RwLock::new(Session {
+                token: token.to_string(),
+                generation: 0,
+            }),
+            relogin: AsyncMutex::new(()),
+            creds: Credentials::default(),
+            client: Client::new(),
+            instance: Arc::new(instance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{self, Reply};
+
+    #[tokio::test]
+    async fn denied_requests_log_in_again() {
+        let (mut client, requests) = stub::serve(|line| match line.contains("login") {
+            // the login form again means the stored credentials are wrong
+            true => Reply::Page(
+                r#"<form action="login"><input id="LoginForm_context" name="context">
+                <input id="LoginForm_username" name="user"></form>"#
+                    .to_string(),
+            ),
+            false => Reply::Status(403),
+        })
+        .await;
+        let mut instance = (*client.instance).clone();
+        instance.login_url = format!("{}login", instance.base_url);
+        client.instance = Arc::new(instance);
+
+        let result = client.get_page("goto.php?target=file_42_download").await;
+        assert!(matches!(result, Err(ClientError::BadCredentials)));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("GET /login"));
+        assert!(requests[2].starts_with("POST /login"));
+    }
+
+    #[tokio::test]
+    async fn redirects_to_other_pages_are_followed() {
+        let (client, requests) = stub::serve(|line| match line.contains("goto.php") {
+            true => Reply::Redirect("ilias.php?baseClass=ilRepositoryGUI".to_string()),
+            false => Reply::Page("<h1>Kurs</h1>".to_string()),
+        })
+        .await;
+        let html = client.get_page("goto.php?target=crs_42").await.unwrap();
+        assert!(html.html().contains("Kurs"));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
 }
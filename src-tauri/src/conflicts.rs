@@ -0,0 +1,190 @@
+use crate::{
+    arena::NodeId,
+    ilias::{walk, FileMeta, IlNodeType, WrappedNode},
+    integrity,
+    settings::VersionSettings,
+    versions,
+};
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A new remote version arrived for a file that was edited locally.
+/// The remote version was put next to the local one.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Conflict {
+    /// Version the local edits are based on
+    pub local_version: usize,
+    pub remote_version: usize,
+    /// Where the remote version was downloaded to
+    pub remote_path: PathBuf,
+    pub remote_meta: FileMeta,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    /// Delete the remote copy
+    KeepLocal,
+    /// Replace the local file with the remote copy, the local edits are kept as earlier version
+    TakeRemote,
+    /// Keep both files, the remote copy is no longer tracked
+    KeepBoth,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConflictEntry {
+    pub id: NodeId,
+    pub uri: String,
+    pub title: String,
+    pub path: PathBuf,
+    pub conflict: Conflict,
+}
+
+/// `folie.pdf` -> `folie (remote v4).pdf`
+pub fn remote_path(path: &Path, version: usize) -> PathBuf {
+    let mut name = OsString::from(path.file_stem().unwrap_or_default());
+    name.push(format!(" (remote v{version})"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Deletes the remote copy of a conflict that a newer remote version replaced.
+/// A copy that was edited since it was downloaded is left in place.
+pub fn discard_remote(conflict: &Conflict) {
+    let path = &conflict.remote_path;
+    match integrity::check(path, &conflict.remote_meta) {
+        None => match fs::remove_file(path) {
+            Ok(()) => info!("removed {path:?}, a newer remote version replaced it"),
+            Err(err) => warn!("can't remove {path:?}: {err}"),
+        },
+        Some(_) => info!("keeping {path:?}, it was edited"),
+    }
+}
+
+/// All unresolved conflicts below `root`
+pub fn list(root: &WrappedNode) -> Vec<ConflictEntry> {
+    let mut conflicts = vec![];
+    walk(root, &mut |_, node| {
+        if let IlNodeType::File {
+            path,
+            conflict: Some(conflict),
+            ..
+        } = &node.breed
+        {
+            conflicts.push(ConflictEntry {
+                id: node.id,
+                uri: node.uri.clone(),
+                title: node.title.clone(),
+                path: path.clone(),
+                conflict: conflict.clone(),
+            });
+        }
+    });
+    conflicts
+}
+
+/// Applies `resolution` to the conflicted file `node`
+pub fn resolve(
+    node: &WrappedNode,
+    resolution: Resolution,
+    settings: &VersionSettings,
+) -> Result<()> {
+    let mut node = node.lock().unwrap();
+    let IlNodeType::File {
+        path,
+        meta,
+        conflict,
+        ..
+    } = &mut node.breed
+    else {
+        return Err(anyhow!("only files can have conflicts"));
+    };
+    // the conflict is only cleared once the files are in place
+    let current = conflict
+        .clone()
+        .ok_or(anyhow!("{path:?} has no conflict"))?;
+
+    match resolution {
+        Resolution::KeepLocal => {
+            if current.remote_path.exists() {
+                fs::remove_file(&current.remote_path)?;
+            }
+        }
+        Resolution::TakeRemote => {
+            let archived = versions::archive(path, current.local_version, settings)?;
+            if let Err(err) = fs::rename(&current.remote_path, &*path) {
+                // put the local edits back
+                if let Some(archived) = archived {
+                    fs::rename(archived, &*path)?;
+                }
+                return Err(err.into());
+            }
+            *meta = Some(current.remote_meta);
+            if let Err(err) = versions::prune(path, settings) {
+                warn!("can't prune old versions of {path:?}: {err}");
+            }
+        }
+        Resolution::KeepBoth => {
+            // the local edits are what the next version is compared with
+            *meta = Some(integrity::fingerprint(path)?);
+        }
+    }
+    *conflict = None;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ilias::IlNode;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn a_failed_resolution_keeps_the_conflict_and_the_local_file() {
+        let dir =
+            std::env::temp_dir().join(format!("better-ilias-conflicts-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("folie.pdf");
+        fs::write(&path, "local edits").unwrap();
+        let conflict = Conflict {
+            local_version: 1,
+            remote_version: 2,
+            // the remote copy went missing
+            remote_path: remote_path(&path, 2),
+            remote_meta: FileMeta {
+                sha256: String::new(),
+                size: 0,
+                downloaded_at: 0,
+            },
+        };
+        let node = Arc::new(Mutex::new(IlNode {
+            breed: IlNodeType::File {
+                path: path.clone(),
+                version: 2,
+                local: true,
+                original_name: None,
+                meta: None,
+                conflict: Some(conflict.clone()),
+                size: None,
+                over_limit: false,
+            },
+            ..Default::default()
+        }));
+
+        let settings = VersionSettings::default();
+        assert!(resolve(&node, Resolution::TakeRemote, &settings).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "local edits");
+        assert!(versions::list(&path, &settings).unwrap().is_empty());
+        assert_eq!(list(&node).len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
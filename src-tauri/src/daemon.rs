@@ -0,0 +1,175 @@
+use crate::{
+    ilias::IliasTree,
+    settings::{Schedule, ScheduleSettings},
+    tree::{CourseFilter, TreeError},
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use croner::Cron;
+use log::{info, warn};
+use std::{collections::HashSet, str::FromStr, sync::Arc};
+
+/// Wait after the first failed run, doubled with every further failure
+const BACKOFF: TimeDelta = TimeDelta::minutes(5);
+const MAX_BACKOFF: TimeDelta = TimeDelta::hours(6);
+
+enum When {
+    Interval(TimeDelta),
+    Cron(Box<Cron>),
+    Never,
+}
+
+impl When {
+    fn parse(schedule: &Schedule) -> Result<Self> {
+        Ok(match schedule {
+            Schedule::Interval(0) => {
+                return Err(anyhow!("the sync interval must be at least a minute"))
+            }
+            Schedule::Interval(minutes) => When::Interval(TimeDelta::minutes(*minutes as i64)),
+            Schedule::Cron(pattern) => When::Cron(Box::new(
+                Cron::from_str(pattern).map_err(|err| anyhow!("invalid cron {pattern}: {err}"))?,
+            )),
+            Schedule::Never => When::Never,
+        })
+    }
+
+    fn after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            When::Interval(interval) => Some(time + *interval),
+            When::Cron(cron) => cron.find_next_occurrence(&time, false).ok(),
+            When::Never => None,
+        }
+    }
+}
+
+/// Courses that are synced together on the same schedule
+struct Job {
+    name: String,
+    filter: CourseFilter,
+    when: When,
+    next: Option<DateTime<Local>>,
+    failures: u32,
+}
+
+impl Job {
+    fn new(name: String, filter: CourseFilter, schedule: &Schedule) -> Result<Self> {
+        let when = When::parse(schedule)?;
+        // interval jobs start right away, cron jobs wait for their first slot
+        let next = match when {
+            When::Interval(_) => Some(Local::now()),
+            _ => when.after(Local::now()),
+        };
+        Ok(Self {
+            name,
+            filter,
+            when,
+            next,
+            failures: 0,
+        })
+    }
+
+    fn schedule_next(&mut self, now: DateTime<Local>) {
+        let next = self.when.after(now);
+        self.next = match self.failures {
+            0 => next,
+            failures => {
+                let backoff = BACKOFF * 2i32.saturating_pow(failures - 1);
+                let retry = now + backoff.min(MAX_BACKOFF);
+                // the next regular run comes first if the backoff grew past it
+                next.map(|next| next.min(retry))
+            }
+        };
+    }
+}
+
+fn jobs(settings: &ScheduleSettings) -> Result<Vec<Job>> {
+    let mut jobs = settings
+        .courses
+        .iter()
+        .map(|(course, schedule)| {
+            let filter = CourseFilter::Only(HashSet::from([course.clone()]));
+            Job::new(course.clone(), filter, schedule)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let others = CourseFilter::Except(settings.courses.keys().cloned().collect());
+    jobs.push(Job::new(
+        "all courses".to_string(),
+        others,
+        &settings.default,
+    )?);
+    Ok(jobs)
+}
+
+/// Syncs `tree` on the schedules from the settings until the process ends.
+/// Runs that are due while another sync is still going are skipped.
+pub async fn run(tree: Arc<IliasTree>) -> Result<()> {
+    let mut jobs = jobs(&tree.settings().schedule)?;
+    loop {
+        let Some(next) = jobs.iter().filter_map(|job| job.next).min() else {
+            return Err(anyhow!("no course is scheduled to sync"));
+        };
+        if let Ok(wait) = (next - Local::now()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+
+        let now = Local::now();
+        for job in jobs
+            .iter_mut()
+            .filter(|job| job.next.is_some_and(|next| next <= now))
+        {
+            info!("syncing {}", job.name);
+            match tree.update_courses(job.filter.clone()).await {
+                Ok(_) => job.failures = 0,
+                Err(TreeError::AlreadyRunning) => info!("skipping {}, a sync is running", job.name),
+                Err(err) => {
+                    job.failures += 1;
+                    warn!("syncing {} failed {} times: {err}", job.name, job.failures);
+                }
+            }
+            if let Err(err) = tree.save() {
+                warn!("can't save the tree: {err}");
+            }
+            job.schedule_next(Local::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(schedule: Schedule) -> Job {
+        Job::new("test".to_string(), CourseFilter::All, &schedule).unwrap()
+    }
+
+    #[test]
+    fn retries_before_the_next_run() {
+        let now = Local::now();
+        let mut job = job(Schedule::Interval(60));
+        job.failures = 1;
+        job.schedule_next(now);
+        assert_eq!(job.next, Some(now + BACKOFF));
+
+        job.failures = 5;
+        job.schedule_next(now);
+        assert_eq!(job.next, Some(now + TimeDelta::minutes(60)));
+    }
+
+    #[test]
+    fn never_retries_jobs_that_never_run() {
+        let mut job = job(Schedule::Never);
+        job.failures = 1;
+        job.schedule_next(Local::now());
+        assert_eq!(job.next, None);
+    }
+
+    #[test]
+    fn rejects_empty_intervals() {
+        assert!(Job::new(
+            "test".to_string(),
+            CourseFilter::All,
+            &Schedule::Interval(0)
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::settings::DownloadSettings;
+use futures::future::BoxFuture;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, Mutex as AsyncMutex, Semaphore},
+    time::{sleep_until, Instant},
+};
+
+/// Order in which waiting downloads get a free slot, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Files that were never downloaded
+    New,
+    /// Files ilias published a new version of
+    Update,
+    /// Files the user explicitly asked for
+    Requested,
+}
+
+struct Job {
+    priority: Priority,
+    /// Submission order, so jobs of the same priority run first come first served
+    seq: usize,
+    run: BoxFuture<'static, ()>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Runs downloads with bounded concurrency, best priority first.
+pub struct DownloadQueue {
+    pending: Mutex<BinaryHeap<Job>>,
+    slots: Semaphore,
+    seq: AtomicUsize,
+    throttle: Option<Throttle>,
+}
+
+impl DownloadQueue {
+    pub fn new(settings: &DownloadSettings) -> Arc<Self> {
+        Arc::new(Self {
+            pending: Mutex::new(BinaryHeap::new()),
+            slots: Semaphore::new(settings.max_concurrent.max(1)),
+            seq: AtomicUsize::new(0),
+            throttle: settings.bandwidth_limit.map(Throttle::new),
+        })
+    }
+
+    /// Bandwidth limit all downloads of this queue share
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    /// Queues `job` and returns a receiver for its result.
+    pub fn submit<F>(self: &Arc<Self>, priority: Priority, job: F) -> oneshot::Receiver<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().push(Job {
+            priority,
+            seq: self.seq.fetch_add(1, AtomicOrdering::Relaxed),
+            run: Box::pin(async move {
+                tx.send(job.await).ok();
+            }),
+        });
+
+        // Every submission frees exactly one job once it gets a slot, which isn't
+        // necessarily its own but always the best one waiting.
+        let queue = self.clone();
+        tokio::spawn(async move {
+            let _slot = queue.slots.acquire().await.expect("queue is never closed");
+            let job = queue.pending.lock().unwrap().pop();
+            if let Some(job) = job {
+                job.run.await;
+            }
+        });
+        rx
+    }
+}
+
+/// Spreads the transferred bytes so that they don't exceed a rate
+pub struct Throttle {
+    bytes_per_sec: u64,
+    /// Point in time at which all bytes passed so far are paid off
+    free_at: AsyncMutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            free_at: AsyncMutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `bytes` more can be passed without exceeding the rate
+    pub async fn consume(&self, bytes: usize) {
+        let until = {
+            let mut free_at = self.free_at.lock().await;
+            let start = (*free_at).max(Instant::now());
+            *free_at = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            *free_at
+        };
+        sleep_until(until).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_concurrent: usize) -> Arc<DownloadQueue> {
+        DownloadQueue::new(&DownloadSettings {
+            max_concurrent,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_the_best_priority_first() {
+        let queue = queue(1);
+        let (open, gate) = oneshot::channel::<()>();
+        let blocker = queue.submit(Priority::New, async move {
+            gate.await.ok();
+        });
+        // let the blocker take the only slot
+        tokio::task::yield_now().await;
+
+        let order = Arc::new(Mutex::new(vec![]));
+        let jobs: Vec<_> = [
+            (Priority::New, "new 1"),
+            (Priority::Update, "update"),
+            (Priority::Requested, "requested"),
+            (Priority::New, "new 2"),
+        ]
+        .into_iter()
+        .map(|(priority, name)| {
+            let order = order.clone();
+            queue.submit(priority, async move { order.lock().unwrap().push(name) })
+        })
+        .collect();
+        open.send(()).unwrap();
+
+        blocker.await.unwrap();
+        for job in jobs {
+            job.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["requested", "update", "new 1", "new 2"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn never_runs_more_than_max_concurrent() {
+        let queue = queue(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let jobs: Vec<_> = (0..8)
+            .map(|_| {
+                let (running, most) = (running.clone(), most.clone());
+                queue.submit(Priority::New, async move {
+                    let now = running.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+                    most.fetch_max(now, AtomicOrdering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, AtomicOrdering::SeqCst);
+                })
+            })
+            .collect();
+        for job in jobs {
+            job.await.unwrap();
+        }
+        assert_eq!(most.load(AtomicOrdering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,422 @@
+use crate::{
+    arena::NodeId,
+    ilias::{IlNodeType, WrappedNode},
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use lazy_static::lazy_static;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref ASSIGNMENTS: Selector = Selector::parse(".il_VAccordionInnerContainer").unwrap();
+    static ref ASSIGNMENT_TITLE: Selector = Selector::parse(".il_VAccordionHead").unwrap();
+    static ref PROPERTIES: Selector = Selector::parse(".form-group").unwrap();
+    static ref PROPERTY_LABEL: Selector =
+        Selector::parse(".il_InfoScreenProperty, .control-label").unwrap();
+    static ref PROPERTY_VALUE: Selector = Selector::parse(".il_InfoScreenPropertyValue").unwrap();
+    static ref INSTRUCTION_FILES: Selector =
+        Selector::parse("a[href*='cmd=downloadFile']").unwrap();
+    static ref ANY_LINK: Selector = Selector::parse("a[href*='ass_id=']").unwrap();
+    static ref SUBMIT_LINK: Selector =
+        Selector::parse("a[href*='cmd=submissionScreen'], a[href*='ilexsubmissiongui']").unwrap();
+}
+
+/// Whether we handed something in for an assignment
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum Submission {
+    /// The page didn't say
+    #[default]
+    Unknown,
+    Missing,
+    Submitted {
+        /// When the last file was handed in
+        at: Option<DateTime<Local>>,
+    },
+}
+
+/// What the exercise page tells about an assignment
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
+pub struct AssignmentInfo {
+    pub start: Option<DateTime<Local>>,
+    pub deadline: Option<DateTime<Local>>,
+    /// Whether the assignment has to be passed to pass the exercise
+    pub mandatory: Option<bool>,
+    pub submission: Submission,
+    /// Page to hand in files, missing for assignments without file submissions
+    #[serde(default)]
+    pub submit_uri: Option<String>,
+}
+
+/// An assignment as listed on the exercise page
+pub struct ParsedAssignment {
+    /// Unique among the assignments of the exercise
+    pub id: String,
+    pub title: String,
+    pub info: AssignmentInfo,
+    /// Instruction files as `(name, uri)`
+    pub files: Vec<(String, String)>,
+}
+
+/// An assignment with its deadline, as shown in the app
+#[derive(Debug, Serialize, Clone)]
+pub struct Deadline {
+    pub id: NodeId,
+    pub uri: String,
+    pub course: String,
+    pub exercise: String,
+    pub title: String,
+    #[serde(flatten)]
+    pub info: AssignmentInfo,
+}
+
+fn text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ")
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn month(name: &str) -> Option<u32> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    let months: [&[&str]; 12] = [
+        &["jan"],
+        &["feb"],
+        &["mär", "mrz", "mar"],
+        &["apr"],
+        &["mai", "may"],
+        &["jun"],
+        &["jul"],
+        &["aug"],
+        &["sep"],
+        &["okt", "oct"],
+        &["nov"],
+        &["dez", "dec"],
+    ];
+    let position = months
+        .iter()
+        .position(|names| names.iter().any(|short| name.starts_with(short)))?;
+    Some(position as u32 + 1)
+}
+
+/// Parses dates as ilias shows them: `24. Okt 2026, 23:55`, `Freitag, 24. Oktober 2026, 23:55`,
+/// `24.10.2026 23:55` or relative ones like `Heute, 23:55` / `Tomorrow, 12:00`.
+/// Anything in parentheses, like the remaining time, is skipped.
+pub fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDateTime> {
+    let text = text.split('(').next()?.trim();
+    let time = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|part| NaiveTime::parse_from_str(part, "%H:%M").ok())
+        .next_back()
+        .unwrap_or(NaiveTime::MIN);
+
+    let lowercase = text.to_lowercase();
+    let relative = [
+        (["heute", "today"], 0),
+        (["morgen", "tomorrow"], 1),
+        (["gestern", "yesterday"], -1),
+    ];
+    for (names, offset) in relative {
+        if names.iter().any(|name| lowercase.starts_with(name)) {
+            return Some((today + TimeDelta::days(offset)).and_time(time));
+        }
+    }
+
+    // `24.10.2026`
+    let numeric = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .find_map(|part| NaiveDate::parse_from_str(part, "%d.%m.%Y").ok());
+    if let Some(date) = numeric {
+        return Some(date.and_time(time));
+    }
+
+    // `24. Okt 2026`, possibly after a weekday
+    let words = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    words.windows(3).find_map(|window| {
+        let day = window[0].trim_end_matches('.').parse().ok()?;
+        let month = month(window[1])?;
+        let year = window[2].parse().ok()?;
+        Some(NaiveDate::from_ymd_opt(year, month, day)?.and_time(time))
+    })
+}
+
+fn local(date: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&date).earliest()
+}
+
+/// The properties of an assignment panel as `(label, value element)`
+fn properties(panel: ElementRef) -> impl Iterator<Item = (String, ElementRef)> {
+    panel.select(&PROPERTIES).filter_map(|property| {
+        let label = collapse_whitespace(&text(property.select(&PROPERTY_LABEL).next()?));
+        let value = property.select(&PROPERTY_VALUE).next()?;
+        Some((label, value))
+    })
+}
+
+fn parse_assignment(panel: ElementRef, index: usize, today: NaiveDate) -> Option<ParsedAssignment> {
+    let title = collapse_whitespace(&text(panel.select(&ASSIGNMENT_TITLE).next()?));
+    let id = panel
+        .select(&ANY_LINK)
+        .find_map(|link| {
+            let url = Url::parse("http://ilias/")
+                .ok()?
+                .join(link.value().attr("href")?)
+                .ok()?;
+            let (_, id) = url.query_pairs().find(|(key, _)| key == "ass_id")?;
+            Some(id.to_string())
+        })
+        .unwrap_or_else(|| index.to_string());
+
+    let mut info = AssignmentInfo {
+        submit_uri: panel
+            .select(&SUBMIT_LINK)
+            .find_map(|link| link.value().attr("href"))
+            .map(str::to_string),
+        ..Default::default()
+    };
+    let mut files = vec![];
+    for (name, value) in properties(panel) {
+        let label = name.trim_end_matches(':').trim().to_lowercase();
+        let value_text = collapse_whitespace(&text(value));
+        let date = || parse_date(&value_text, today).and_then(local);
+        // labels are matched exactly, `Verbleibende Bearbeitungszeit` isn't the deadline
+        let is = |names: &[&str]| names.contains(&label.as_str());
+
+        if is(&["startzeit", "start time", "beginn", "start"]) {
+            info.start = date().or(info.start);
+        } else if is(&[
+            "abgabetermin",
+            "bearbeitung bis",
+            "deadline",
+            "edit until",
+            "ende",
+            "endzeit",
+            "end time",
+        ]) {
+            info.deadline = date().or(info.deadline);
+        } else if is(&["verpflichtend", "mandatory", "pflicht", "pflichtabgabe"]) {
+            let value = value_text.to_lowercase();
+            info.mandatory = Some(value.starts_with("ja") || value.starts_with("yes"));
+        } else if is(&[
+            "abgegebene dateien",
+            "submitted files",
+            "abgabe",
+            "submission",
+        ]) {
+            let value = value_text.to_lowercase();
+            let missing = ["keine", "noch nicht", "not submitted", "no file", "nothing"]
+                .iter()
+                .any(|marker| value.contains(marker));
+            info.submission = match missing {
+                true => Submission::Missing,
+                false => Submission::Submitted { at: date() },
+            };
+        }
+
+        for link in value.select(&INSTRUCTION_FILES) {
+            let Some(uri) = link.value().attr("href") else {
+                continue;
+            };
+            // files are usually listed with their name as label and a download button as value
+            let name = match name.contains('.') {
+                true => name.clone(),
+                false => collapse_whitespace(&text(link)),
+            };
+            if !files.iter().any(|(_, known)| known == uri) {
+                files.push((name, uri.to_string()));
+            }
+        }
+    }
+    Some(ParsedAssignment {
+        id,
+        title,
+        info,
+        files,
+    })
+}
+
+/// The assignments on the exercise page `html`
+pub fn parse_assignments(html: &Html) -> Vec<ParsedAssignment> {
+    let today = Local::now().date_naive();
+    html.select(&ASSIGNMENTS)
+        .enumerate()
+        .filter_map(|(index, panel)| parse_assignment(panel, index, today))
+        .collect()
+}
+
+/// Uri an assignment node gets, ilias has no page for single assignments
+pub fn assignment_uri(exercise: &str, id: &str) -> String {
+    format!("{exercise}#ass_{id}")
+}
+
+/// Assignments below `root` whose deadline lies between `from` and `until`, soonest first
+pub fn deadlines(
+    root: &WrappedNode,
+    from: Option<DateTime<Local>>,
+    until: Option<DateTime<Local>>,
+) -> Vec<Deadline> {
+    fn visit(
+        node: &WrappedNode,
+        titles: &[String],
+        deadlines: &mut Vec<Deadline>,
+        matches: &dyn Fn(&AssignmentInfo) -> bool,
+    ) {
+        let node = node.lock().unwrap();
+        if let IlNodeType::Assignment { info, .. } = &node.breed {
+            if matches(info) {
+                deadlines.push(Deadline {
+                    id: node.id,
+                    uri: node.uri.clone(),
+                    course: titles.get(1).cloned().unwrap_or_default(),
+                    exercise: titles.last().cloned().unwrap_or_default(),
+                    title: node.title.clone(),
+                    info: info.clone(),
+                });
+            }
+            return;
+        }
+        let mut titles = titles.to_vec();
+        titles.push(node.title.clone());
+        for child in node.children.iter().flatten() {
+            visit(child, &titles, deadlines, matches);
+        }
+    }
+    let matches = |info: &AssignmentInfo| {
+        info.deadline.is_some_and(|deadline| {
+            from.is_none_or(|from| deadline >= from) && until.is_none_or(|until| deadline <= until)
+        })
+    };
+    let mut deadlines = vec![];
+    visit(root, &[], &mut deadlines, &matches);
+    deadlines.sort_by_key(|deadline| deadline.info.deadline);
+    deadlines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 18).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn property(label: &str, value: &str) -> String {
+        format!(
+            r#"<div class="form-group">
+                <div class="il_InfoScreenProperty">{label}</div>
+                <div class="il_InfoScreenPropertyValue">{value}</div>
+            </div>"#
+        )
+    }
+
+    fn assignment(properties: &[String]) -> ParsedAssignment {
+        let html = Html::parse_document(&format!(
+            r#"<div class="il_VAccordionInnerContainer">
+                <div class="il_VAccordionHead">Blatt 3</div>
+                <a href="ilias.php?ass_id=42&cmd=submissionScreen">Abgeben</a>
+                {}
+            </div>"#,
+            properties.concat()
+        ));
+        let panel = html.select(&ASSIGNMENTS).next().unwrap();
+        parse_assignment(panel, 0, today()).unwrap()
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        assert_eq!(
+            parse_date("24. Okt 2026, 23:55", today()),
+            Some(at(2026, 10, 24, 23, 55))
+        );
+        assert_eq!(
+            parse_date("Freitag, 24. Oktober 2026, 23:55", today()),
+            Some(at(2026, 10, 24, 23, 55))
+        );
+        assert_eq!(
+            parse_date("3. Mär. 2027 (noch 4 Monate)", today()),
+            Some(at(2027, 3, 3, 0, 0))
+        );
+        assert_eq!(
+            parse_date("24.10.2026 08:15", today()),
+            Some(at(2026, 10, 24, 8, 15))
+        );
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        assert_eq!(
+            parse_date("Heute, 23:55", today()),
+            Some(at(2026, 10, 18, 23, 55))
+        );
+        assert_eq!(
+            parse_date("Tomorrow, 12:00", today()),
+            Some(at(2026, 10, 19, 12, 0))
+        );
+        assert_eq!(
+            parse_date("Gestern, 09:30", today()),
+            Some(at(2026, 10, 17, 9, 30))
+        );
+        assert_eq!(parse_date("3 Tage, 4 Stunden", today()), None);
+    }
+
+    #[test]
+    fn parses_assignments() {
+        let parsed = assignment(&[
+            property("Startzeit", "10. Okt 2026, 10:00"),
+            property("Abgabetermin", "24. Okt 2026, 23:55"),
+            property("Verbleibende Bearbeitungszeit", "6 Tage, 4 Stunden"),
+            property("Verpflichtend", "Ja"),
+            property("Abgegebene Dateien", "Keine Dateien abgegeben"),
+            property(
+                "blatt3.pdf",
+                r#"<a href="ilias.php?ass_id=42&cmd=downloadFile&file=a">Download</a>"#,
+            ),
+        ]);
+        assert_eq!(parsed.id, "42");
+        assert_eq!(parsed.title, "Blatt 3");
+        assert_eq!(
+            parsed.info,
+            AssignmentInfo {
+                start: local(at(2026, 10, 10, 10, 0)),
+                deadline: local(at(2026, 10, 24, 23, 55)),
+                mandatory: Some(true),
+                submission: Submission::Missing,
+                submit_uri: Some("ilias.php?ass_id=42&cmd=submissionScreen".to_string()),
+            }
+        );
+        assert_eq!(
+            parsed.files,
+            [(
+                "blatt3.pdf".to_string(),
+                "ilias.php?ass_id=42&cmd=downloadFile&file=a".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn unreadable_dates_keep_the_deadline() {
+        let parsed = assignment(&[
+            property("Abgabetermin:", "24. Okt 2026, 23:55"),
+            property("Ende", "nach Absprache"),
+            property("Abgabe", "blatt3.pdf, 20. Okt 2026, 18:00"),
+        ]);
+        assert_eq!(parsed.info.deadline, local(at(2026, 10, 24, 23, 55)));
+        assert_eq!(
+            parsed.info.submission,
+            Submission::Submitted {
+                at: local(at(2026, 10, 20, 18, 0))
+            }
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use crate::download_queue::Throttle;
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use reqwest::{
+    header::{ACCEPT_RANGES, CONTENT_RANGE, RANGE},
+    RequestBuilder, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+/// Digest and size of a completed download
+pub struct Downloaded {
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Hidden sibling of `path` the body is streamed into before it is moved into place
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Number of bytes an earlier, interrupted download of `path` already wrote
+pub async fn resume_offset(path: &Path) -> u64 {
+    fs::metadata(part_path(path))
+        .await
+        .map(|meta| meta.len())
+        .unwrap_or(0)
+}
+
+/// Throws away the partial download of `path` so the next attempt starts from zero
+pub async fn discard_part(path: &Path) {
+    fs::remove_file(part_path(path)).await.ok();
+}
+
+/// Only asks for the bytes after `offset` if an earlier attempt got that far
+pub fn with_range(request: RequestBuilder, offset: u64) -> RequestBuilder {
+    if offset == 0 {
+        request
+    } else {
+        request.header(RANGE, format!("bytes={offset}-"))
+    }
+}
+
+/// Start of the range in a `Content-Range: bytes <start>-<end>/<size>` header
+fn range_start(resp: &Response) -> Option<u64> {
+    let range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = range.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn accepts_ranges(resp: &Response) -> bool {
+    resp.headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("bytes"))
+}
+
+/// Streams the body of `resp` into a temporary file next to `path`, syncs it to disk and renames
+/// it to `path`. An interrupted download therefore never leaves a truncated file at `path`.
+///
+/// `offset` is the [resume_offset] the request was made with. If the server answers the range
+/// request, the body is appended to the partial file, if it ignores it, the download starts over.
+/// `on_bytes` is called with the bytes on disk and the expected total after every chunk.
+pub async fn download_to(
+    resp: Response,
+    path: &Path,
+    offset: u64,
+    throttle: Option<&Throttle>,
+    on_bytes: &mut (dyn FnMut(u64, Option<u64>) + Send),
+) -> Result<Downloaded> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("can't create {parent:?}"))?;
+    }
+
+    let part = part_path(path);
+    let append = match resp.status() {
+        StatusCode::PARTIAL_CONTENT if offset > 0 && range_start(&resp) == Some(offset) => true,
+        StatusCode::PARTIAL_CONTENT => {
+            fs::remove_file(&part).await.ok();
+            bail!("server answered with an unexpected range for {path:?}")
+        }
+        StatusCode::OK => false,
+        status => bail!("can't download {path:?}, server answered with {status}"),
+    };
+
+    // keep the partial file only if we can continue it later
+    let resumable = append || accepts_ranges(&resp);
+    let written = if append { offset } else { 0 };
+    let total = resp.content_length().map(|len| len + written);
+    on_bytes(written, total);
+    let mut on_chunk = |bytes| on_bytes(written + bytes, total);
+    let downloaded = match stream_into(resp, &part, append, throttle, &mut on_chunk).await {
+        Ok(downloaded) => downloaded,
+        Err(err) => {
+            if !resumable {
+                fs::remove_file(&part).await.ok();
+            }
+            return Err(err);
+        }
+    };
+    fs::rename(&part, path)
+        .await
+        .with_context(|| format!("can't move download to {path:?}"))?;
+    Ok(downloaded)
+}
+
+async fn stream_into(
+    resp: Response,
+    part: &Path,
+    append: bool,
+    throttle: Option<&Throttle>,
+    on_chunk: &mut (dyn FnMut(u64) + Send),
+) -> Result<Downloaded> {
+    // the digest covers the whole file, including what an earlier attempt wrote
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    if append {
+        let mut existing = File::open(part).await?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = existing.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+            size += len as u64;
+        }
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+        .with_context(|| format!("can't open {part:?}"))?;
+    let mut body = resp.bytes_stream();
+    let mut written = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if let Some(throttle) = throttle {
+            throttle.consume(chunk.len()).await;
+        }
+        file.write_all(&chunk).await?;
+        hasher.update(&chunk);
+        written += chunk.len() as u64;
+        on_chunk(written);
+    }
+    file.sync_all().await?;
+    Ok(Downloaded {
+        sha256: hex::encode(hasher.finalize()),
+        size: size + written,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    const BODY: &[u8] = b"%PDF-1.5 \x00\xff\xfe binary lecture slides \x89PNG\r\n\x1a\n";
+
+    #[derive(Clone, Copy)]
+    struct Stub {
+        /// answer `Range` requests with 206
+        ranges: bool,
+        /// close the connection after this many body bytes
+        cut_after: Option<usize>,
+    }
+
+    /// Serves [BODY] over plain http and records the `Range` header of every request
+    async fn serve(stub: Stub) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let mut len = 0;
+                while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                    len += socket.read(&mut buf[len..]).await.unwrap();
+                }
+                let request = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| range.trim_end_matches('-').to_string());
+                seen.lock().unwrap().push(range.clone());
+
+                let start = match range {
+                    Some(start) if stub.ranges => start.parse::<usize>().unwrap(),
+                    _ => 0,
+                };
+                let mut head = if start > 0 {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{}/{}\r\n",
+                        BODY.len() - 1,
+                        BODY.len()
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\n".to_string()
+                };
+                if stub.ranges {
+                    head += "Accept-Ranges: bytes\r\n";
+                }
+                head += &format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    BODY.len() - start
+                );
+                let end = stub.cut_after.map_or(BODY.len(), |cut| start + cut);
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&BODY[start..end]).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        (url, requests)
+    }
+
+    fn target(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("better-ilias-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(part_path(&path)).ok();
+        path
+    }
+
+    async fn fetch(url: &str, path: &Path) -> Result<Downloaded> {
+        let offset = resume_offset(path).await;
+        let resp = with_range(Client::new().get(url), offset).send().await?;
+        download_to(resp, path, offset, None, &mut |_, _| {}).await
+    }
+
+    #[tokio::test]
+    async fn download_is_binary_safe() {
+        let (url, requests) = serve(Stub {
+            ranges: false,
+            cut_after: None,
+        })
+        .await;
+        let path = target("full.pdf");
+
+        fetch(&url, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), BODY);
+        assert!(!part_path(&path).exists());
+        assert_eq!(*requests.lock().unwrap(), vec![None]);
+    }
+
+    #[tokio::test]
+    async fn resumes_with_range_request() {
+        let path = target("resume.pdf");
+        std::fs::write(part_path(&path), &BODY[..10]).unwrap();
+        let (url, requests) = serve(Stub {
+            ranges: true,
+            cut_after: None,
+        })
+        .await;
+
+        let downloaded = fetch(&url, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), BODY);
+        assert_eq!(*requests.lock().unwrap(), vec![Some("10".to_string())]);
+        assert_eq!(downloaded.size, BODY.len() as u64);
+        assert_eq!(downloaded.sha256, hex::encode(Sha256::digest(BODY)));
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_ignored() {
+        let path = target("ignored.pdf");
+        std::fs::write(part_path(&path), &BODY[..10]).unwrap();
+        let (url, _) = serve(Stub {
+            ranges: false,
+            cut_after: None,
+        })
+        .await;
+
+        fetch(&url, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), BODY);
+    }
+
+    #[tokio::test]
+    async fn keeps_interrupted_download_for_resume() {
+        let path = target("interrupted.pdf");
+        let (url, _) = serve(Stub {
+            ranges: true,
+            cut_after: Some(12),
+        })
+        .await;
+        assert!(fetch(&url, &path).await.is_err());
+        assert!(!path.exists());
+        assert_eq!(resume_offset(&path).await, 12);
+
+        let (url, requests) = serve(Stub {
+            ranges: true,
+            cut_after: None,
+        })
+        .await;
+        fetch(&url, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), BODY);
+        assert_eq!(*requests.lock().unwrap(), vec![Some("12".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn drops_interrupted_download_without_range_support() {
+        let path = target("no-ranges.pdf");
+        let (url, _) = serve(Stub {
+            ranges: false,
+            cut_after: Some(12),
+        })
+        .await;
+        assert!(fetch(&url, &path).await.is_err());
+        assert!(!path.exists());
+        assert_eq!(resume_offset(&path).await, 0);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::tree::path_segment;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Extensions of the mime types commonly found on ilias
+const MIME_EXTENSIONS: &[(&str, &str)] = &[
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/x-zip-compressed", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/msword", "doc"),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+    ("application/vnd.oasis.opendocument.presentation", "odp"),
+    ("application/json", "json"),
+    ("application/x-ipynb+json", "ipynb"),
+    ("application/x-tex", "tex"),
+    ("text/plain", "txt"),
+    ("text/csv", "csv"),
+    ("text/html", "html"),
+    ("text/markdown", "md"),
+    ("text/x-python", "py"),
+    ("text/x-java-source", "java"),
+    ("text/x-c", "c"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+    ("image/webp", "webp"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+    ("audio/mpeg", "mp3"),
+];
+
+/// Leading bytes of common file formats
+const MAGIC_BYTES: &[(&[u8], &str)] = &[
+    (b"%PDF", "pdf"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"\xff\xd8\xff", "jpg"),
+    (b"GIF8", "gif"),
+    (b"\x1f\x8b", "gz"),
+    (b"7z\xbc\xaf\x27\x1c", "7z"),
+    (b"Rar!", "rar"),
+    (b"ID3", "mp3"),
+    (b"\x1a\x45\xdf\xa3", "webm"),
+];
+
+/// What the response headers tell about the name of a file
+pub struct ResponseName {
+    /// Name of the file as it was uploaded
+    pub original_name: Option<String>,
+    pub extension: Option<String>,
+}
+
+impl ResponseName {
+    /// Takes the extension from `Content-Disposition` first, then from the mime type
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let original_name = disposition_filename(headers);
+        let extension = original_name
+            .as_deref()
+            .and_then(|name| Path::new(name).extension()?.to_str())
+            .or_else(|| mime_extension(headers))
+            .map(str::to_string);
+        Self {
+            original_name,
+            extension,
+        }
+    }
+
+    /// Where the file at `path` is stored: under its original name next to `path`.
+    /// Without one, or if another file took the name already, `path` gets the extension.
+    pub fn apply(&self, path: &Path) -> PathBuf {
+        let named = self
+            .original_name
+            .as_deref()
+            .map(|name| path.with_file_name(path_segment(name)));
+        match named {
+            Some(named) if named == path || !named.exists() => named,
+            _ => self.apply_extension(path),
+        }
+    }
+
+    /// `path` with the resolved extension
+    pub fn apply_extension(&self, path: &Path) -> PathBuf {
+        match &self.extension {
+            Some(extension) => with_extension(path, extension),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+/// The parameters of a `Content-Disposition` value with lowercase keys, unquoted values
+fn disposition_params(disposition: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = disposition.chars().peekable();
+    // the disposition type
+    chars.by_ref().find(|&c| c == ';');
+    loop {
+        let key: String = chars.by_ref().take_while(|&c| c != '=').collect();
+        let key = key.trim().to_lowercase();
+        if key.is_empty() {
+            return params;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+            chars.by_ref().find(|&c| c == ';');
+        } else {
+            value = chars.by_ref().take_while(|&c| c != ';').collect();
+            value = value.trim().to_string();
+        }
+        params.push((key, value));
+    }
+}
+
+/// Decodes an extended value `charset'language'percent-encoded`
+fn decode_extended(value: &str) -> Option<String> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_, encoded) = rest.split_once('\'')?;
+    let bytes = urlencoding::decode_binary(encoded.as_bytes());
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes.into_owned()).ok()
+    } else {
+        // iso-8859-1 maps every byte to the code point of the same value
+        Some(bytes.iter().map(|&byte| byte as char).collect())
+    }
+}
+
+/// Filename the server suggests in `Content-Disposition`, preferring the extended `filename*`
+pub fn disposition_filename(headers: &HeaderMap) -> Option<String> {
+    // ilias sends names with umlauts as raw utf-8, which `to_str` rejects
+    let disposition = std::str::from_utf8(headers.get(CONTENT_DISPOSITION)?.as_bytes()).ok()?;
+    let params = disposition_params(disposition);
+    let extended = params
+        .iter()
+        .find(|(key, _)| key == "filename*")
+        .and_then(|(_, value)| decode_extended(value));
+    let name = extended.or_else(|| {
+        params
+            .iter()
+            .find(|(key, _)| key == "filename")
+            .map(|(_, value)| value.clone())
+    })?;
+
+    // never let the server pick the directory
+    let name = name.rsplit(['/', '\\']).next()?.trim().to_string();
+    (!name.is_empty() && name != "." && name != "..").then_some(name)
+}
+
+pub fn mime_extension(headers: &HeaderMap) -> Option<&'static str> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let mime = content_type.split(';').next()?.trim().to_lowercase();
+    MIME_EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == mime)
+        .map(|(_, extension)| *extension)
+}
+
+/// Guesses the extension of a downloaded file by its first bytes
+pub fn sniff_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    let mut head = [0; 512];
+    let len = File::open(path)?.read(&mut head)?;
+    let head = &head[..len];
+
+    let known = MAGIC_BYTES
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, extension)| *extension);
+    let mp4 = head.len() > 8 && &head[4..8] == b"ftyp";
+    Ok(known
+        .or(mp4.then_some("mp4"))
+        .or(is_text(head).then_some("txt")))
+}
+
+fn is_text(head: &[u8]) -> bool {
+    let utf8 = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // the head may end in the middle of a character
+        Err(err) => err.error_len().is_none(),
+    };
+    utf8 && !head.is_empty() && !head.contains(&0)
+}
+
+/// Adds `extension` to the file name of `path` unless it already ends with it.
+/// Unlike [Path::with_extension] this keeps dots that belong to the title (`Kapitel_2.1`).
+pub fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    if path
+        .extension()
+        .is_some_and(|current| current.eq_ignore_ascii_case(extension))
+    {
+        return path.to_path_buf();
+    }
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(disposition: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_bytes(disposition.as_bytes()).unwrap(),
+        );
+        headers
+    }
+
+    fn filename(disposition: &str) -> Option<String> {
+        disposition_filename(&headers(disposition))
+    }
+
+    #[test]
+    fn parses_plain_and_quoted_filenames() {
+        assert_eq!(
+            filename("attachment; filename=notes.pdf").unwrap(),
+            "notes.pdf"
+        );
+        assert_eq!(
+            filename(r#"attachment; filename="Blatt 1; Lösung.pdf"; size=42"#).unwrap(),
+            "Blatt 1; Lösung.pdf"
+        );
+        assert_eq!(
+            filename(r#"inline;filename="say \"hi\".txt""#).unwrap(),
+            r#"say "hi".txt"#
+        );
+        assert_eq!(filename("attachment"), None);
+        assert_eq!(filename(r#"attachment; filename="""#), None);
+    }
+
+    #[test]
+    fn prefers_the_extended_filename() {
+        assert_eq!(
+            filename(r#"attachment; filename="Ubung.pdf"; filename*=UTF-8''%C3%9Cbung%201.pdf"#)
+                .unwrap(),
+            "Übung 1.pdf"
+        );
+        assert_eq!(
+            filename("attachment; filename*=iso-8859-1'de'%DCbung.pdf").unwrap(),
+            "Übung.pdf"
+        );
+    }
+
+    #[test]
+    fn strips_directories() {
+        assert_eq!(
+            filename(r#"attachment; filename="../../.bashrc""#).unwrap(),
+            ".bashrc"
+        );
+        assert_eq!(
+            filename(r#"attachment; filename="C:\\Users\\x\\a.txt""#).unwrap(),
+            "a.txt"
+        );
+        assert_eq!(filename(r#"attachment; filename="..""#), None);
+    }
+
+    #[test]
+    fn names_files_after_the_original_name() {
+        let name = ResponseName::from_headers(&headers(r#"attachment; filename="Blatt 3.pdf""#));
+        // a directory of its own, a `Blatt_3.pdf` left in the shared one would change the name
+        let dir =
+            std::env::temp_dir().join(format!("better-ilias-file-type-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("no-such-title");
+        assert_eq!(name.extension.as_deref(), Some("pdf"));
+        assert_eq!(name.apply(&path), dir.join("Blatt_3.pdf"));
+        assert_eq!(name.apply_extension(&path), dir.join("no-such-title.pdf"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_the_mime_type() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/pdf; charset=binary"),
+        );
+        let name = ResponseName::from_headers(&headers);
+        assert_eq!(name.original_name, None);
+        assert_eq!(
+            name.apply(Path::new("Kapitel_2.1")),
+            PathBuf::from("Kapitel_2.1.pdf")
+        );
+    }
+}
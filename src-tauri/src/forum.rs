@@ -0,0 +1,570 @@
+use crate::{
+    ilias::{IlNodeType, WrappedNode},
+    instance::IliasInstance,
+    tree::path_segment,
+};
+use anyhow::{anyhow, Result};
+use dirs::cache_dir;
+use lazy_static::lazy_static;
+use log::warn;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+lazy_static! {
+    static ref THREAD_LINK: Selector = Selector::parse("a[href*='thr_pk=']").unwrap();
+    static ref POSTS: Selector = Selector::parse(".ilFrmPostRow").unwrap();
+    static ref POST_ANCHOR: Selector = Selector::parse("a[id^='fpost_']").unwrap();
+    static ref POST_TITLE: Selector = Selector::parse(".ilFrmPostTitle").unwrap();
+    static ref POST_AUTHOR: Selector = Selector::parse(
+        ".ilFrmPostHeader .ilProfileLink, .ilFrmPostHeader a[href*='showUserProfile']"
+    )
+    .unwrap();
+    static ref POST_DETAILS: Selector = Selector::parse(".ilFrmPostHeader .small").unwrap();
+    static ref POST_CONTENT: Selector = Selector::parse(".ilFrmPostContent").unwrap();
+    static ref POST_ATTACHMENTS: Selector =
+        Selector::parse(".ilFrmPostAttachmentsContainer a[href]").unwrap();
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Post {
+    /// Id of the post in ilias
+    pub id: String,
+    pub title: String,
+    pub author: Option<String>,
+    /// As shown by ilias, e.g. `12. Okt 2026, 10:15`
+    pub posted_at: Option<String>,
+    /// Converted to Markdown
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+    /// Tracked locally, new posts start out unread
+    #[serde(default)]
+    pub read: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Thread {
+    pub uri: String,
+    pub title: String,
+    pub posts: Vec<Post>,
+    /// What the forum page listed as the last post when the posts were crawled
+    #[serde(default)]
+    pub last_post: Option<String>,
+}
+
+/// A thread as listed on the page of its forum
+#[derive(Debug, PartialEq)]
+pub struct ListedThread {
+    pub uri: String,
+    pub title: String,
+    /// Link and date of the last post, they change with every new post
+    pub last_post: Option<String>,
+}
+
+impl Thread {
+    pub fn unread(&self) -> usize {
+        self.posts.iter().filter(|post| !post.read).count()
+    }
+}
+
+/// Threads of all crawled forums by the uri of the forum, kept in `forums.json`
+/// next to the save instead of in the tree
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ForumStore(HashMap<String, Vec<Thread>>);
+
+fn forums_path() -> Option<PathBuf> {
+    cache_dir().map(|mut path| {
+        path.push("better-ilias/forums.json");
+        path
+    })
+}
+
+impl ForumStore {
+    pub fn load() -> Self {
+        let Some(path) = forums_path().filter(|path| path.exists()) else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str(&data)?))
+        {
+            Ok(store) => store,
+            Err(err) => {
+                warn!("can't load forums from {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = forums_path().ok_or(anyhow!("can't create path"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn threads(&self, forum: &str) -> &[Thread] {
+        self.0.get(forum).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The forum containing the thread at `uri` and the thread itself
+    pub fn thread(&self, uri: &str) -> Option<(&str, &Thread)> {
+        self.0.iter().find_map(|(forum, threads)| {
+            let thread = threads.iter().find(|thread| thread.uri == uri)?;
+            Some((forum.as_str(), thread))
+        })
+    }
+
+    /// Replaces the threads of `forum` with freshly crawled ones, keeping the read state of
+    /// the posts that were known before
+    pub fn merge(&mut self, forum: &str, mut threads: Vec<Thread>) {
+        let read = self
+            .threads(forum)
+            .iter()
+            .flat_map(|thread| &thread.posts)
+            .filter(|post| post.read)
+            .map(|post| post.id.clone())
+            .collect::<HashSet<_>>();
+        for post in threads.iter_mut().flat_map(|thread| &mut thread.posts) {
+            post.read = read.contains(&post.id);
+        }
+        self.0.insert(forum.to_string(), threads);
+    }
+
+    /// Marks the post `id` of the thread at `uri`, or all of its posts, as read or unread
+    pub fn mark_read(&mut self, uri: &str, id: Option<&str>, read: bool) -> Result<()> {
+        let thread = self
+            .0
+            .values_mut()
+            .flatten()
+            .find(|thread| thread.uri == uri)
+            .ok_or(anyhow!("no thread with uri {uri}"))?;
+        let mut found = false;
+        for post in &mut thread.posts {
+            if id.is_none_or(|id| post.id == id) {
+                post.read = read;
+                found = true;
+            }
+        }
+        match (found, id) {
+            (false, Some(id)) => Err(anyhow!("no post {id} in {uri}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ")
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Threads listed on the forum page `html`, without their posts
+pub fn parse_threads(html: &Html) -> Vec<ListedThread> {
+    let mut threads: Vec<ListedThread> = vec![];
+    // index of each thread in `threads` by its id
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    for link in html.select(&THREAD_LINK) {
+        let Some(uri) = link.value().attr("href") else {
+            continue;
+        };
+        let Some(id) = Url::parse("http://ilias/")
+            .ok()
+            .and_then(|base| base.join(uri).ok())
+            .and_then(|url| {
+                let (_, id) = url.query_pairs().find(|(key, _)| key == "thr_pk")?;
+                Some(id.to_string())
+            })
+        else {
+            continue;
+        };
+        let text = collapse_whitespace(&text(link));
+        // the first link is the title, later ones point to the last post
+        match ids.get(&id) {
+            Some(&index) => threads[index].last_post = Some(format!("{uri} {text}")),
+            None if !text.is_empty() => {
+                ids.insert(id, threads.len());
+                threads.push(ListedThread {
+                    uri: uri.to_string(),
+                    title: text,
+                    last_post: None,
+                });
+            }
+            None => {}
+        }
+    }
+    threads
+}
+
+/// Posts on the thread page `html`, in the order they are shown
+pub fn parse_posts(html: &Html) -> Vec<Post> {
+    html.select(&POSTS)
+        .enumerate()
+        .map(|(index, post)| {
+            let author = post
+                .select(&POST_AUTHOR)
+                .next()
+                .map(|author| collapse_whitespace(&text(author)));
+            // the details read `author | date`, or only the date for deleted users
+            let details = post
+                .select(&POST_DETAILS)
+                .next()
+                .map(|details| collapse_whitespace(&text(details)));
+            let posted_at = details
+                .as_deref()
+                .and_then(|details| details.rsplit('|').next())
+                .map(str::trim)
+                .filter(|date| !date.is_empty())
+                .map(str::to_string);
+            let id = post
+                .select(&POST_ANCHOR)
+                .next()
+                .and_then(|anchor| anchor.value().attr("id"))
+                .and_then(|id| id.strip_prefix("fpost_"))
+                .map(str::to_string)
+                .unwrap_or_else(|| {
+                    format!(
+                        "{}-{}-{index}",
+                        author.as_deref().unwrap_or_default(),
+                        posted_at.as_deref().unwrap_or_default()
+                    )
+                });
+            Post {
+                id,
+                title: post
+                    .select(&POST_TITLE)
+                    .next()
+                    .map(|title| collapse_whitespace(&text(title)))
+                    .unwrap_or_default(),
+                author,
+                posted_at,
+                body: post
+                    .select(&POST_CONTENT)
+                    .next()
+                    .map(markdown)
+                    .unwrap_or_default(),
+                attachments: post
+                    .select(&POST_ATTACHMENTS)
+                    .filter_map(|link| {
+                        Some(Attachment {
+                            name: collapse_whitespace(&text(link)),
+                            uri: link.value().attr("href")?.to_string(),
+                        })
+                    })
+                    .collect(),
+                read: false,
+            }
+        })
+        .collect()
+}
+
+/// Converts the html of a post to Markdown, keeping paragraphs, lists, links and emphasis
+fn markdown(element: ElementRef) -> String {
+    fn convert(element: ElementRef, out: &mut String) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                let collapsed = collapse_whitespace(text);
+                if text.starts_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+                    out.push(' ');
+                }
+                out.push_str(&collapsed);
+                if text.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+                    out.push(' ');
+                }
+                continue;
+            }
+            let Some(child) = ElementRef::wrap(child) else {
+                continue;
+            };
+            match child.value().name() {
+                "br" => out.push('\n'),
+                "p" | "div" | "h1" | "h2" | "h3" | "h4" | "pre" => {
+                    convert(child, out);
+                    out.push_str("\n\n");
+                }
+                "ul" | "ol" => {
+                    convert(child, out);
+                    out.push('\n');
+                }
+                "li" => {
+                    out.push_str("- ");
+                    convert(child, out);
+                    out.push('\n');
+                }
+                "strong" | "b" => {
+                    out.push_str("**");
+                    convert(child, out);
+                    out.push_str("**");
+                }
+                "em" | "i" => {
+                    out.push('_');
+                    convert(child, out);
+                    out.push('_');
+                }
+                "a" => {
+                    let mut label = String::new();
+                    convert(child, &mut label);
+                    match child.value().attr("href") {
+                        Some(href) => out.push_str(&format!("[{}]({href})", label.trim())),
+                        None => out.push_str(&label),
+                    }
+                }
+                "blockquote" => {
+                    let mut quote = String::new();
+                    convert(child, &mut quote);
+                    for line in quote.trim().lines() {
+                        out.push_str(&format!("> {line}\n"));
+                    }
+                    out.push('\n');
+                }
+                "script" | "style" => {}
+                _ => convert(child, out),
+            }
+        }
+    }
+    let mut out = String::new();
+    convert(element, &mut out);
+
+    let mut text = String::new();
+    let mut blank = 0;
+    for line in out.lines().map(str::trim) {
+        blank = if line.is_empty() { blank + 1 } else { 0 };
+        if blank < 2 {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Renders `thread` of the forum `forum_title` as a Markdown document
+pub fn to_markdown(forum_title: &str, thread: &Thread, instance: &IliasInstance) -> String {
+    let mut out = format!("# {}\n\n_{forum_title}_\n", thread.title);
+    for post in &thread.posts {
+        out.push_str(&format!("\n---\n\n## {}\n\n", post.title));
+        let byline = [post.author.as_deref(), post.posted_at.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !byline.is_empty() {
+            out.push_str(&format!("**{byline}**\n\n"));
+        }
+        out.push_str(&post.body);
+        out.push('\n');
+        if !post.attachments.is_empty() {
+            out.push_str("\nAttachments:\n");
+            for attachment in &post.attachments {
+                out.push_str(&format!(
+                    "- [{}]({})\n",
+                    attachment.name,
+                    instance.url(&attachment.uri)
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// The folder the forum at `uri` lives in and the title of the forum
+pub fn location(root: &WrappedNode, uri: &str) -> Option<(PathBuf, String)> {
+    let guard = root.lock().unwrap();
+    for child in guard.children.iter().flatten() {
+        let child_guard = child.lock().unwrap();
+        if child_guard.uri != uri {
+            continue;
+        }
+        return match (&guard.breed, &child_guard.breed) {
+            (IlNodeType::Folder { path, .. }, IlNodeType::Forum) => {
+                Some((path.clone(), child_guard.title.clone()))
+            }
+            _ => None,
+        };
+    }
+    guard
+        .children
+        .iter()
+        .flatten()
+        .find_map(|child| location(child, uri))
+}
+
+/// Writes the thread at `uri` as Markdown to `<forum>/<thread>.md` in the folder of its
+/// forum and returns the path of the file
+pub fn export(
+    store: &ForumStore,
+    root: &WrappedNode,
+    uri: &str,
+    instance: &IliasInstance,
+) -> Result<PathBuf> {
+    let (forum, thread) = store
+        .thread(uri)
+        .ok_or(anyhow!("no thread with uri {uri}"))?;
+    let (folder, forum_title) =
+        location(root, forum).ok_or(anyhow!("the forum of {uri} is not in a folder"))?;
+    let mut path = folder.join(path_segment(&forum_title));
+    fs::create_dir_all(&path)?;
+    path.push(format!("{}.md", path_segment(&thread.title)));
+    fs::write(&path, to_markdown(&forum_title, thread, instance))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post_html(id: &str, title: &str, author: &str, date: &str, content: &str) -> String {
+        format!(
+            r#"<div class="ilFrmPostRow">
+                <a id="fpost_{id}"></a>
+                <div class="ilFrmPostHeader">
+                    <h3 class="ilFrmPostTitle">{title}</h3>
+                    <span class="small"><a class="ilProfileLink">{author}</a> | {date}</span>
+                </div>
+                <div class="ilFrmPostContent">{content}</div>
+            </div>"#
+        )
+    }
+
+    fn convert(html: &str) -> String {
+        let html = Html::parse_fragment(&format!("<div id='post'>{html}</div>"));
+        let post = html
+            .select(&Selector::parse("#post").unwrap())
+            .next()
+            .unwrap();
+        markdown(post)
+    }
+
+    #[test]
+    fn lists_threads_with_their_last_post() {
+        let html = Html::parse_document(
+            r#"<table>
+                <tr>
+                    <td><a href="ilias.php?thr_pk=11&cmd=viewThread">Frage zu
+                        Blatt 1</a></td>
+                    <td><a href="ilias.php?thr_pk=11&pos_pk=7#fpost_7">12. Okt 2026, 10:15</a></td>
+                </tr>
+                <tr>
+                    <td><a href="ilias.php?thr_pk=12&cmd=viewThread">Klausur</a></td>
+                </tr>
+            </table>"#,
+        );
+        assert_eq!(
+            parse_threads(&html),
+            [
+                ListedThread {
+                    uri: "ilias.php?thr_pk=11&cmd=viewThread".to_string(),
+                    title: "Frage zu Blatt 1".to_string(),
+                    last_post: Some(
+                        "ilias.php?thr_pk=11&pos_pk=7#fpost_7 12. Okt 2026, 10:15".to_string()
+                    ),
+                },
+                ListedThread {
+                    uri: "ilias.php?thr_pk=12&cmd=viewThread".to_string(),
+                    title: "Klausur".to_string(),
+                    last_post: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_posts() {
+        let html = Html::parse_document(&format!(
+            "{}{}",
+            post_html("7", "Frage", "Anna", "12. Okt 2026, 10:15", "<p>Wie?</p>"),
+            r#"<div class="ilFrmPostRow">
+                <div class="ilFrmPostHeader">
+                    <h3 class="ilFrmPostTitle">Antwort</h3>
+                    <span class="small">13. Okt 2026, 09:00</span>
+                </div>
+                <div class="ilFrmPostContent">So.</div>
+                <div class="ilFrmPostAttachmentsContainer">
+                    <a href="ilias.php?cmd=getFile&file=1">loesung.pdf</a>
+                </div>
+            </div>"#
+        ));
+        let posts = parse_posts(&html);
+        assert_eq!(
+            posts[0],
+            Post {
+                id: "7".to_string(),
+                title: "Frage".to_string(),
+                author: Some("Anna".to_string()),
+                posted_at: Some("12. Okt 2026, 10:15".to_string()),
+                body: "Wie?".to_string(),
+                attachments: vec![],
+                read: false,
+            }
+        );
+        // posts without anchor and author still get an id that is stable across syncs
+        assert_eq!(posts[1].id, "-13. Okt 2026, 09:00-1");
+        assert_eq!(posts[1].author, None);
+        assert_eq!(
+            posts[1].attachments,
+            [Attachment {
+                name: "loesung.pdf".to_string(),
+                uri: "ilias.php?cmd=getFile&file=1".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn converts_posts_to_markdown() {
+        assert_eq!(
+            convert("<p>Hallo <b>alle</b>,</p><p>siehe <a href='https://x.org'>hier</a>.</p>"),
+            "Hallo **alle**,\n\nsiehe [hier](https://x.org)."
+        );
+        assert_eq!(
+            convert("<ul><li>eins</li><li><i>zwei</i></li></ul>Zeile<br>neue Zeile"),
+            "- eins\n- _zwei_\n\nZeile\nneue Zeile"
+        );
+        assert_eq!(
+            convert("<blockquote><p>Zitat</p></blockquote><script>x()</script>Antwort"),
+            "> Zitat\n\nAntwort"
+        );
+    }
+
+    #[test]
+    fn merging_keeps_the_read_state() {
+        let thread = |posts: &[(&str, bool)]| Thread {
+            uri: "thread".to_string(),
+            title: "Thread".to_string(),
+            posts: posts
+                .iter()
+                .map(|(id, read)| Post {
+                    id: id.to_string(),
+                    title: String::new(),
+                    author: None,
+                    posted_at: None,
+                    body: String::new(),
+                    attachments: vec![],
+                    read: *read,
+                })
+                .collect(),
+            last_post: None,
+        };
+        let mut store = ForumStore::default();
+        store.merge("forum", vec![thread(&[("1", false), ("2", false)])]);
+        store.mark_read("thread", Some("1"), true).unwrap();
+        store.merge(
+            "forum",
+            vec![thread(&[("1", false), ("2", false), ("3", false)])],
+        );
+        assert_eq!(store.threads("forum")[0].unread(), 2);
+        assert!(store.threads("forum")[0].posts[0].read);
+        assert!(store.mark_read("thread", Some("4"), true).is_err());
+    }
+}
@@ -1,22 +1,44 @@
 use crate::{
+    arena::{NodeArena, NodeId},
+    calendar,
+    changes::{self, ChangeSet},
     client::{ClientError, Credentials, IliasClient},
-    tree::{update_root, TreeError},
+    conflicts::{self, Conflict, ConflictEntry, Resolution},
+    download_queue::{DownloadQueue, Priority},
+    exercise::{self, AssignmentInfo, Deadline, Submission},
+    forum::{self, ForumStore, Thread},
+    iliasignore::IgnoreRules,
+    instance::IliasInstance,
+    integrity::{self, IntegrityIssue},
+    policy::{self, SyncPolicy},
+    progress::Progress,
+    save,
+    search::{SearchHit, SearchIndex},
+    settings::{Quality, Settings},
+    submission::SubmittedFile,
+    tree::{update_root, CourseFilter, SyncContext, TreeError},
+    versions::{self, FileVersion},
+    video::VideoInfo,
 };
+use anyhow::anyhow;
+use chrono::{DateTime, Local};
 use dirs::cache_dir;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs, io,
-    path::PathBuf,
+    fs::{self, TryLockError},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
+use tokio::sync::Mutex as AsyncMutex;
 
+/// Default root listing, see [IliasInstance::root_uri]
 pub const ILIAS_ROOT: &str =
     "ilias.php?cmdClass=ilmembershipoverviewgui&cmdNode=ku&baseClass=ilmembershipoverviewgui";
 
 pub const ROOT_PATH: &str = "studium/";
 
-type WrappedNode = Arc<Mutex<IlNode>>;
+pub type WrappedNode = Arc<Mutex<IlNode>>;
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct IlNode {
     pub uri: String,
@@ -24,6 +46,62 @@ pub struct IlNode {
     pub breed: IlNodeType,
     pub visible: bool,
     pub children: Option<Vec<WrappedNode>>,
+    /// Excluded from the sync by an `.iliasignore`
+    #[serde(default)]
+    pub ignored: bool,
+    /// Shown below the title in the listing
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Stays the same across syncs, given out by the [NodeArena]
+    #[serde(default)]
+    pub id: NodeId,
+    /// Id of the node listing this one, `None` for the root
+    #[serde(default)]
+    pub parent: Option<NodeId>,
+}
+
+/// Calls `f` with `node` and every node below it, parents before their children.
+/// `f` gets the node already locked and must not lock it again.
+pub fn walk(node: &WrappedNode, f: &mut dyn FnMut(&WrappedNode, &mut IlNode)) {
+    let children = {
+        let mut guard = node.lock().unwrap();
+        f(node, &mut guard);
+        guard.children.clone().unwrap_or_default()
+    };
+    for child in &children {
+        walk(child, f);
+    }
+}
+
+/// A node without what is below it, as the app gets it. `children` counts the children,
+/// they are fetched by the node's id when it is opened.
+#[derive(Debug, Serialize, Clone)]
+pub struct NodeView {
+    pub id: NodeId,
+    pub parent: Option<NodeId>,
+    pub uri: String,
+    pub title: String,
+    pub breed: IlNodeType,
+    pub visible: bool,
+    pub ignored: bool,
+    pub description: Option<String>,
+    pub children: Option<usize>,
+}
+
+impl From<&IlNode> for NodeView {
+    fn from(node: &IlNode) -> Self {
+        Self {
+            id: node.id,
+            parent: node.parent,
+            uri: node.uri.clone(),
+            title: node.title.clone(),
+            breed: node.breed.clone(),
+            visible: node.visible,
+            ignored: node.ignored,
+            description: node.description.clone(),
+            children: node.children.as_ref().map(Vec::len),
+        }
+    }
 }
 
 impl Default for IlNode {
@@ -34,6 +112,10 @@ impl Default for IlNode {
             breed: IlNodeType::Root,
             visible: true,
             children: Some(vec![]),
+            ignored: false,
+            description: None,
+            id: 0,
+            parent: None,
         }
     }
 }
@@ -42,22 +124,82 @@ impl Default for IlNode {
 pub enum IlNodeType {
     Forum,
     Folder {
-        store_files: bool,
         path: PathBuf,
+        /// Inherited from the parent if not set
+        #[serde(default)]
+        policy: Option<SyncPolicy>,
     },
     DirectLink,
     File {
         path: PathBuf,
         version: usize,
         local: bool,
+        /// Name of the file as it was uploaded to ilias
+        #[serde(default)]
+        original_name: Option<String>,
+        /// What was written to `path` by the last download
+        #[serde(default)]
+        meta: Option<FileMeta>,
+        /// Set if a new version arrived while `path` had local edits
+        #[serde(default)]
+        conflict: Option<Conflict>,
+        /// Size in bytes as listed by ilias or sent by the server
+        #[serde(default)]
+        size: Option<u64>,
+        /// Set if `local` was turned off because the file exceeds the download limits,
+        /// instead of by a policy. The limits are checked again on every sync.
+        #[serde(default)]
+        over_limit: bool,
+    },
+    Video {
+        /// Where the recording is stored once downloaded
+        path: PathBuf,
+        #[serde(default)]
+        meta: Option<FileMeta>,
+        /// Resolved from the video page on the first crawl
+        #[serde(default)]
+        info: Option<VideoInfo>,
     },
-    Video,
+    /// Its children are the assignments
     Exercise,
+    /// Part of an exercise, its children are the instruction files
+    Assignment {
+        /// Where the instruction files are stored
+        path: PathBuf,
+        #[serde(default)]
+        info: AssignmentInfo,
+    },
+    /// A dated meeting of a course
+    Session {
+        #[serde(default)]
+        start: Option<DateTime<Local>>,
+        #[serde(default)]
+        end: Option<DateTime<Local>>,
+        #[serde(default)]
+        location: Option<String>,
+    },
     Group,
     Root,
 }
 
+/// Fingerprint of a downloaded file
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct FileMeta {
+    /// Hex encoded SHA-256 of the content
+    pub sha256: String,
+    pub size: u64,
+    /// Unix timestamp in seconds
+    pub downloaded_at: u64,
+}
+
 impl IlNodeType {
+    pub fn file_path(&self) -> Option<&PathBuf> {
+        if let Self::File { path, .. } = self {
+            Some(path)
+        } else {
+            None
+        }
+    }
     pub fn path_mut(&mut self) -> Option<&mut PathBuf> {
         if let Self::File { path, .. } = self {
             Some(path)
@@ -65,6 +207,27 @@ impl IlNodeType {
             None
         }
     }
+    pub fn meta_mut(&mut self) -> Option<&mut Option<FileMeta>> {
+        if let Self::File { meta, .. } = self {
+            Some(meta)
+        } else {
+            None
+        }
+    }
+    pub fn version_mut(&mut self) -> Option<&mut usize> {
+        if let Self::File { version, .. } = self {
+            Some(version)
+        } else {
+            None
+        }
+    }
+    pub fn size(&self) -> Option<u64> {
+        if let Self::File { size, .. } = self {
+            *size
+        } else {
+            None
+        }
+    }
     pub fn get_local(&mut self) -> Option<&mut bool> {
         if let Self::File { local, .. } = self {
             Some(local)
@@ -72,6 +235,16 @@ impl IlNodeType {
             None
         }
     }
+    /// Marks a file as only available remotely because it exceeds the download limits
+    pub fn exceed_limits(&mut self) {
+        if let Self::File {
+            local, over_limit, ..
+        } = self
+        {
+            *local = false;
+            *over_limit = true;
+        }
+    }
 
     pub fn path(&self) -> Option<PathBuf> {
         if let IlNodeType::Folder { path, .. } = self {
@@ -81,52 +254,156 @@ impl IlNodeType {
         }
     }
 
+    /// Where the node is stored on disk
+    pub fn local_path(&self) -> Option<PathBuf> {
+        match self {
+            Self::Folder { path, .. }
+            | Self::File { path, .. }
+            | Self::Video { path, .. }
+            | Self::Assignment { path, .. } => Some(path.clone()),
+            _ => None,
+        }
+    }
+
     pub fn is_file(&self) -> bool {
         matches!(self, IlNodeType::File { .. })
     }
 }
 
+/// Locks the file all processes sync under, so that the app and the daemon never sync at the
+/// same time. Syncs go ahead unlocked if the lock file can't be used.
+fn lock_sync() -> Result<Option<fs::File>, TreeError> {
+    let Some(path) = cache_dir().map(|path| path.join("better-ilias/sync.lock")) else {
+        return Ok(None);
+    };
+    let file = fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+    });
+    let file = match file {
+        Ok(file) => file,
+        Err(err) => {
+            warn!("can't open the sync lock {path:?}: {err}");
+            return Ok(None);
+        }
+    };
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Err(TreeError::AlreadyRunning),
+        Err(TryLockError::Error(err)) => {
+            warn!("can't lock {path:?}: {err}");
+            Ok(None)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct IliasTree {
     tree: WrappedNode,
     client: Arc<Mutex<Option<Arc<IliasClient>>>>,
-}
-
-fn saves_path() -> Option<PathBuf> {
-    cache_dir().map(|mut path| {
-        path.push("better-ilias/save.json");
-        path
-    })
+    instance: Arc<IliasInstance>,
+    queue: Arc<DownloadQueue>,
+    progress: Arc<Progress>,
+    settings: Arc<Settings>,
+    /// Held for the duration of a sync, together with the lock file of [lock_sync]
+    syncing: Arc<AsyncMutex<()>>,
+    /// What earlier syncs changed, newest first
+    changes: Arc<Mutex<Vec<ChangeSet>>>,
+    forums: Arc<Mutex<ForumStore>>,
+    search: Arc<Mutex<SearchIndex>>,
+    arena: Arc<Mutex<NodeArena>>,
 }
 
 impl IliasTree {
-    pub async fn new() -> Self {
+    pub async fn new(instance: IliasInstance, settings: Settings) -> Self {
+        let tree = Arc::new(Mutex::new(save::load()));
         Self {
-            tree: saves_path()
-                .map(|path| fs::read_to_string(path).ok())
-                .flatten()
-                .map(|data| Arc::new(Mutex::new(serde_json::from_str::<IlNode>(&data).unwrap())))
-                .unwrap_or_default(),
+            arena: Arc::new(Mutex::new(NodeArena::new(&tree))),
+            tree,
             client: Arc::new(Mutex::new(None)),
+            instance: Arc::new(instance),
+            queue: DownloadQueue::new(&settings.downloads),
+            progress: Arc::new(Progress::default()),
+            settings: Arc::new(settings),
+            syncing: Arc::new(AsyncMutex::new(())),
+            changes: Arc::new(Mutex::new(changes::load())),
+            forums: Arc::new(Mutex::new(ForumStore::load())),
+            search: Arc::new(Mutex::new(SearchIndex::load())),
         }
     }
 
-    pub async fn update_root(&self) -> Result<(), TreeError> {
+    pub async fn update_root(&self) -> Result<ChangeSet, TreeError> {
+        self.update_courses(CourseFilter::All).await
+    }
+
+    /// Syncs the courses matching `filter` and returns what changed.
+    /// Fails right away if another sync is running, in this process or another one.
+    pub async fn update_courses(&self, filter: CourseFilter) -> Result<ChangeSet, TreeError> {
+        let Ok(_syncing) = self.syncing.try_lock() else {
+            return Err(TreeError::AlreadyRunning);
+        };
+        let _lock = lock_sync()?;
         let client = self.client.lock().unwrap().clone();
         if let Some(client) = client {
             info!("updating root node");
-            update_root(client.clone(), self.tree.clone())
-                .await
-                .unwrap()?;
+            let ctx = SyncContext {
+                client,
+                ignore: Arc::new(IgnoreRules::load()),
+                forums: self.forums.clone(),
+                queue: self.queue.clone(),
+                progress: self.progress.clone(),
+                settings: self.settings.clone(),
+                arena: self.arena.clone(),
+            };
+            let before = changes::snapshot(&self.tree, &self.forums.lock().unwrap());
+            self.progress.started();
+            let result = update_root(ctx, self.tree.clone(), filter).await.unwrap();
+            self.progress.finished();
+            result?;
             info!("successfully updated root node");
-            Ok(())
+
+            let changes = before.diff(&changes::snapshot(&self.tree, &self.forums.lock().unwrap()));
+            if let Err(err) = changes::record(&mut self.changes.lock().unwrap(), changes.clone()) {
+                warn!("can't save changes: {err}");
+            }
+            self.update_index().await;
+            Ok(changes)
         } else {
             Err(TreeError::Client(ClientError::NoToken))
         }
     }
 
+    /// Indexes what changed in the tree since the last update, reading new downloads
+    async fn update_index(&self) {
+        let (search, tree) = (self.search.clone(), self.tree.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            // files are read without the lock, so searching works during the update
+            let known = search.lock().unwrap().hashes();
+            let pending = SearchIndex::read(&tree, &known);
+            let mut search = search.lock().unwrap();
+            if search.apply(pending) > 0 {
+                search.save()?;
+            }
+            anyhow::Ok(())
+        })
+        .await;
+        match result {
+            Ok(Err(err)) => warn!("can't save search index: {err}"),
+            Err(err) => warn!("updating the search index failed: {err}"),
+            Ok(Ok(())) => {}
+        }
+    }
+
+    /// Nodes matching `query` in their title, description or downloaded file, best first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.search.lock().unwrap().search(query, limit)
+    }
+
     pub async fn login(&self, creds: Credentials) -> Result<(), ClientError> {
-        match IliasClient::with_creds(creds).await {
+        match IliasClient::with_creds(self.instance.clone(), creds).await {
             Ok(client) => *self.client.lock().unwrap() = Some(Arc::new(client)),
             Err(e) => {
                 warn!("{e}");
@@ -137,28 +414,282 @@ impl IliasTree {
     }
 
     pub async fn login_cached(&self) -> anyhow::Result<()> {
-        let client = IliasClient::new().await?;
+        let client = IliasClient::new(self.instance.clone()).await?;
         *self.client.lock().unwrap() = Some(Arc::new(client));
         Ok(())
     }
 
+    pub fn instance(&self) -> &IliasInstance {
+        &self.instance
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
     pub fn is_authenticated(&self) -> bool {
         self.client.lock().unwrap().is_some()
     }
 
+    /// The root with handles to its children, serializing it sends the whole tree
     pub fn get_root_node(&self) -> IlNode {
         self.tree.lock().unwrap().clone()
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
-        let data = self.tree.lock().unwrap();
-        let path = saves_path().ok_or(anyhow::anyhow!("can't create path"))?;
-        if let Err(e) = fs::write(&path, serde_json::to_string(&*data).unwrap()) {
-            if e.kind() == io::ErrorKind::NotFound {
-                fs::create_dir_all(path.parent().unwrap())?;
-                fs::write(path, serde_json::to_string(&*data).unwrap())?;
+    /// The node with the given uri, looked up in the arena
+    pub fn find_node(&self, uri: &str) -> Option<WrappedNode> {
+        self.arena.lock().unwrap().find(uri)
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<WrappedNode> {
+        self.arena.lock().unwrap().get(id)
+    }
+
+    fn expect_node(&self, id: NodeId) -> anyhow::Result<WrappedNode> {
+        self.node(id).ok_or(anyhow!("no node with id {id}"))
+    }
+
+    /// The node with `id` and everything below it
+    pub fn get_node(&self, id: NodeId) -> Option<IlNode> {
+        Some(self.node(id)?.lock().unwrap().clone())
+    }
+
+    /// The node with `id` without its children
+    pub fn view(&self, id: NodeId) -> Option<NodeView> {
+        Some(NodeView::from(&*self.node(id)?.lock().unwrap()))
+    }
+
+    /// The children of the node with `id` without theirs
+    pub fn children(&self, id: NodeId) -> Option<Vec<NodeView>> {
+        let children = self.node(id)?.lock().unwrap().children.clone();
+        Some(
+            children
+                .iter()
+                .flatten()
+                .map(|child| NodeView::from(&*child.lock().unwrap()))
+                .collect(),
+        )
+    }
+
+    fn file_of(&self, id: NodeId) -> anyhow::Result<(WrappedNode, PathBuf, usize)> {
+        let node = self.expect_node(id)?;
+        let (path, version) = {
+            let mut guard = node.lock().unwrap();
+            let path = guard.breed.file_path().cloned();
+            let version = guard.breed.version_mut().copied();
+            path.zip(version)
+                .ok_or(anyhow!("{} is not a file", guard.title))?
+        };
+        Ok((node, path, version))
+    }
+
+    /// Downloads the file with `id` ahead of the sync's downloads, regardless of the limits
+    pub async fn download(&self, id: NodeId) -> anyhow::Result<()> {
+        let client = self
+            .client
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::NoToken)?;
+        let node = self.expect_node(id)?;
+        let uri = {
+            let node = node.lock().unwrap();
+            if !node.breed.is_file() {
+                return Err(anyhow!("{} is not a file", node.title));
             }
+            node.uri.clone()
+        };
+        let (queue, progress) = (self.queue.clone(), self.progress.clone());
+        let job = self.queue.submit(Priority::Requested, async move {
+            let result = client
+                .download_file(node, None, queue.throttle(), &progress)
+                .await;
+            progress.file_finished(&uri, &result);
+            result
+        });
+        job.await?
+    }
+
+    /// Downloads the recording of the video with `id` regardless of the limits,
+    /// in the quality from the settings if `None`
+    pub async fn download_video(&self, id: NodeId, quality: Option<Quality>) -> anyhow::Result<()> {
+        let client = self
+            .client
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::NoToken)?;
+        let node = self.expect_node(id)?;
+        let uri = node.lock().unwrap().uri.clone();
+        let quality = quality.unwrap_or(self.settings.videos.quality);
+        let (queue, progress) = (self.queue.clone(), self.progress.clone());
+        let job = self.queue.submit(Priority::Requested, async move {
+            let result = client
+                .download_video(node, quality, None, queue.throttle(), &progress)
+                .await;
+            progress.file_finished(&uri, &result);
+            result
+        });
+        job.await?
+    }
+
+    /// Metadata and available qualities of the video with `id`, freshly resolved
+    pub async fn video_info(&self, id: NodeId) -> anyhow::Result<VideoInfo> {
+        let client = self
+            .client
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::NoToken)?;
+        let node = self.expect_node(id)?;
+        let uri = node.lock().unwrap().uri.clone();
+        let (info, _) = client.video_sources(&uri).await?;
+        if let IlNodeType::Video { info: known, .. } = &mut node.lock().unwrap().breed {
+            *known = Some(info.clone());
         }
+        Ok(info)
+    }
+
+    /// Earlier versions of the file with `id` that are kept locally
+    pub fn file_versions(&self, id: NodeId) -> anyhow::Result<Vec<FileVersion>> {
+        let (_, path, _) = self.file_of(id)?;
+        Ok(versions::list(&path, &self.settings.versions)?)
+    }
+
+    /// Replaces the local copy of the file with `id` with one of its earlier versions.
+    /// The node then describes the restored copy, so the next sync brings the current
+    /// version back like any other update and keeps the restored one as an earlier version.
+    pub fn restore_version(&self, id: NodeId, version: usize) -> anyhow::Result<()> {
+        let (node, path, current) = self.file_of(id)?;
+        // keeps the fingerprint, edits of the current version stay detectable
+        if version == current {
+            return Ok(());
+        }
+        versions::restore(&path, version, current, &self.settings.versions)?;
+        let restored = integrity::fingerprint(&path)?;
+        let mut node = node.lock().unwrap();
+        *node.breed.version_mut().unwrap() = version;
+        *node.breed.meta_mut().unwrap() = Some(restored);
         Ok(())
     }
+
+    /// Rehashes all downloaded files and reports the ones that differ from what was downloaded
+    pub fn verify(&self) -> Vec<IntegrityIssue> {
+        integrity::verify(&self.tree)
+    }
+
+    pub fn conflicts(&self) -> Vec<ConflictEntry> {
+        conflicts::list(&self.tree)
+    }
+
+    pub fn resolve_conflict(&self, id: NodeId, resolution: Resolution) -> anyhow::Result<()> {
+        conflicts::resolve(&self.expect_node(id)?, resolution, &self.settings.versions)
+    }
+
+    /// Sets the sync policy of the course or folder with `id`, `None` to inherit it
+    pub fn set_policy(&self, id: NodeId, policy: Option<SyncPolicy>) -> anyhow::Result<()> {
+        let (node, ancestors) = {
+            let arena = self.arena.lock().unwrap();
+            let node = arena.get(id).ok_or(anyhow!("no node with id {id}"))?;
+            (node, arena.ancestors(id))
+        };
+        policy::set(&node, policy::inherited(&ancestors), policy)
+    }
+
+    /// What the last syncs changed, newest first
+    pub fn recent_changes(&self) -> Vec<ChangeSet> {
+        self.changes.lock().unwrap().clone()
+    }
+
+    /// Assignments whose deadline lies between `from` and `until`, soonest first
+    pub fn deadlines(
+        &self,
+        from: Option<DateTime<Local>>,
+        until: Option<DateTime<Local>>,
+    ) -> Vec<Deadline> {
+        exercise::deadlines(&self.tree, from, until)
+    }
+
+    /// Hands in `files` for the assignment with `id`, refusing if its deadline passed.
+    /// Returns the files ilias lists as submitted afterwards.
+    pub async fn submit(
+        &self,
+        id: NodeId,
+        files: &[PathBuf],
+    ) -> anyhow::Result<Vec<SubmittedFile>> {
+        let client = self
+            .client
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::NoToken)?;
+        let node = self.expect_node(id)?;
+        let (title, info) = {
+            let node = node.lock().unwrap();
+            let IlNodeType::Assignment { info, .. } = &node.breed else {
+                return Err(anyhow!("{} is not an assignment", node.title));
+            };
+            (node.title.clone(), info.clone())
+        };
+        if let Some(deadline) = info.deadline.filter(|deadline| *deadline < Local::now()) {
+            return Err(anyhow!(
+                "the deadline of {title} passed on {}",
+                deadline.format("%d.%m.%Y %H:%M")
+            ));
+        }
+        let submit_uri = info
+            .submit_uri
+            .ok_or(anyhow!("{title} doesn't take file submissions"))?;
+
+        let submitted = client.submit_files(&submit_uri, files).await?;
+        if let IlNodeType::Assignment { info, .. } = &mut node.lock().unwrap().breed {
+            info.submission = Submission::Submitted {
+                at: Some(Local::now()),
+            };
+        }
+        Ok(submitted)
+    }
+
+    /// Writes the deadlines and sessions of the courses matching `filter` as iCalendar to `path`.
+    /// Returns the number of events.
+    pub fn export_calendar(&self, path: &Path, filter: &CourseFilter) -> anyhow::Result<usize> {
+        let (calendar, events) = calendar::export(&self.tree, filter, &self.instance);
+        fs::write(path, calendar)?;
+        Ok(events)
+    }
+
+    /// The threads of the forum with `id` as of the last sync
+    pub fn forum_threads(&self, id: NodeId) -> anyhow::Result<Vec<Thread>> {
+        // the forums are kept by the uri of the forum
+        let uri = self.expect_node(id)?.lock().unwrap().uri.clone();
+        Ok(self.forums.lock().unwrap().threads(&uri).to_vec())
+    }
+
+    /// Marks the post `id` of the thread at `uri`, or the whole thread, as read or unread
+    pub fn mark_read(&self, uri: &str, id: Option<&str>, read: bool) -> anyhow::Result<()> {
+        let mut forums = self.forums.lock().unwrap();
+        forums.mark_read(uri, id, read)?;
+        forums.save()
+    }
+
+    /// Writes the thread at `uri` as Markdown into the folder of its course
+    pub fn export_thread(&self, uri: &str) -> anyhow::Result<PathBuf> {
+        forum::export(
+            &self.forums.lock().unwrap(),
+            &self.tree,
+            uri,
+            &self.instance,
+        )
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Err(err) = self.forums.lock().unwrap().save() {
+            warn!("can't save forums: {err}");
+        }
+        save::store(&self.tree.lock().unwrap())
+    }
 }
@@ -0,0 +1,230 @@
+use crate::ilias::{IlNode, IlNodeType, ROOT_PATH};
+use dirs::config_dir;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the ignore files, globally in the config dir and in the folder of a course
+pub const IGNORE_FILE: &str = ".iliasignore";
+
+/// The rules of one `.iliasignore`.
+///
+/// Lines are gitignore patterns matched against the node path. A line starting with `@Type`
+/// only applies to nodes of that type (`@Video`, `@Forum`, `@Assignment`, `@File *.zip`, ...).
+#[derive(Default)]
+struct Rules {
+    any: Option<Gitignore>,
+    /// `@Type pattern` lines by lowercase type
+    typed: HashMap<String, Gitignore>,
+    /// `@Type` lines without a pattern
+    types: HashSet<String>,
+}
+
+fn type_name(breed: &IlNodeType) -> &'static str {
+    match breed {
+        IlNodeType::Forum => "forum",
+        IlNodeType::Folder { .. } => "folder",
+        IlNodeType::DirectLink => "directlink",
+        IlNodeType::File { .. } => "file",
+        IlNodeType::Video { .. } => "video",
+        IlNodeType::Exercise => "exercise",
+        IlNodeType::Assignment { .. } => "assignment",
+        IlNodeType::Session { .. } => "session",
+        IlNodeType::Group => "group",
+        IlNodeType::Root => "root",
+    }
+}
+
+impl Rules {
+    fn parse(source: &Path, data: &str) -> Self {
+        let mut any = GitignoreBuilder::new("");
+        let mut typed: HashMap<String, GitignoreBuilder> = HashMap::new();
+        let mut types = HashSet::new();
+        for line in data.lines().map(str::trim) {
+            let result = match line.strip_prefix('@') {
+                Some(rule) => match rule.split_once(char::is_whitespace) {
+                    Some((kind, pattern)) => typed
+                        .entry(kind.to_lowercase())
+                        .or_insert_with(|| GitignoreBuilder::new(""))
+                        .add_line(Some(source.to_path_buf()), pattern.trim())
+                        .map(|_| ()),
+                    None => {
+                        types.insert(rule.to_lowercase());
+                        Ok(())
+                    }
+                },
+                None => any.add_line(Some(source.to_path_buf()), line).map(|_| ()),
+            };
+            if let Err(err) = result {
+                warn!("skipping rule {line:?} in {source:?}: {err}");
+            }
+        }
+        let build = |builder: GitignoreBuilder| {
+            builder
+                .build()
+                .map_err(|err| warn!("can't use the rules in {source:?}: {err}"))
+                .ok()
+        };
+        Self {
+            any: build(any),
+            typed: typed
+                .into_iter()
+                .filter_map(|(kind, builder)| Some((kind, build(builder)?)))
+                .collect(),
+            types,
+        }
+    }
+
+    fn load(source: &Path) -> Option<Self> {
+        let data = fs::read_to_string(source).ok()?;
+        Some(Self::parse(source, &data))
+    }
+
+    /// `path` is relative to the folder of the ignore file
+    fn matches(&self, path: &Path, kind: &str, is_dir: bool) -> bool {
+        let matched =
+            |rules: &Gitignore| rules.matched_path_or_any_parents(path, is_dir).is_ignore();
+        self.types.contains(kind)
+            || self.any.as_ref().is_some_and(matched)
+            || self.typed.get(kind).is_some_and(matched)
+    }
+}
+
+/// All `.iliasignore` rules that apply to a sync
+#[derive(Default)]
+pub struct IgnoreRules {
+    global: Option<Rules>,
+    /// Rules of the courses by their folder under [ROOT_PATH]
+    courses: HashMap<PathBuf, Rules>,
+}
+
+impl IgnoreRules {
+    /// Reads the global ignore file and the ones in the folders of the courses
+    pub fn load() -> Self {
+        let global = config_dir()
+            .map(|dir| dir.join("better-ilias").join(IGNORE_FILE))
+            .and_then(|path| Rules::load(&path));
+        let courses = fs::read_dir(ROOT_PATH)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let course = PathBuf::from(entry.ok()?.file_name());
+                let rules = Rules::load(&Path::new(ROOT_PATH).join(&course).join(IGNORE_FILE))?;
+                Some((course, rules))
+            })
+            .collect();
+        Self { global, courses }
+    }
+
+    /// Whether `node` is excluded from the sync. Nodes without a path of their own
+    /// (forums, exercises, ...) are matched as if they were stored in `parent`.
+    pub fn is_ignored(&self, node: &IlNode, parent: &Path) -> bool {
+        let path = node
+            .breed
+            .local_path()
+            .unwrap_or_else(|| parent.join(&node.title));
+        let Ok(path) = path.strip_prefix(ROOT_PATH) else {
+            return false;
+        };
+        let kind = type_name(&node.breed);
+        let is_dir = !node.breed.is_file();
+
+        if self
+            .global
+            .as_ref()
+            .is_some_and(|rules| rules.matches(path, kind, is_dir))
+        {
+            return true;
+        }
+        let mut components = path.components();
+        let Some(course) = components.next() else {
+            return false;
+        };
+        let below_course = components.as_path();
+        !below_course.as_os_str().is_empty()
+            && self
+                .courses
+                .get(Path::new(&course))
+                .is_some_and(|rules| rules.matches(below_course, kind, is_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(global: &str, course: &str) -> IgnoreRules {
+        let source = Path::new(IGNORE_FILE);
+        IgnoreRules {
+            global: Some(Rules::parse(source, global)),
+            courses: HashMap::from([(PathBuf::from("Kurs"), Rules::parse(source, course))]),
+        }
+    }
+
+    fn node(title: &str, breed: IlNodeType) -> IlNode {
+        IlNode {
+            title: title.to_string(),
+            breed,
+            ..Default::default()
+        }
+    }
+
+    fn file(path: &str) -> IlNode {
+        let path = Path::new(ROOT_PATH).join(path);
+        let title = path.file_name().unwrap().to_string_lossy().to_string();
+        node(
+            &title,
+            IlNodeType::File {
+                path,
+                version: 1,
+                local: true,
+                original_name: None,
+                meta: None,
+                conflict: None,
+                size: None,
+                over_limit: false,
+            },
+        )
+    }
+
+    fn folder(path: &str) -> IlNode {
+        let path = Path::new(ROOT_PATH).join(path);
+        let title = path.file_name().unwrap().to_string_lossy().to_string();
+        node(&title, IlNodeType::Folder { path, policy: None })
+    }
+
+    #[test]
+    fn matches_paths_below_the_root() {
+        let rules = rules("*.mp4\n# comment\nAltklausuren/\n", "");
+        let root = Path::new(ROOT_PATH);
+        assert!(rules.is_ignored(&file("Kurs/Vorlesung/1.mp4"), root));
+        assert!(!rules.is_ignored(&file("Kurs/Vorlesung/1.pdf"), root));
+        assert!(rules.is_ignored(&folder("Kurs/Altklausuren"), root));
+        assert!(rules.is_ignored(&file("Kurs/Altklausuren/2019.pdf"), root));
+    }
+
+    #[test]
+    fn typed_rules_only_apply_to_their_type() {
+        let rules = rules("@File *.zip\n@forum\n", "");
+        let course = Path::new(ROOT_PATH).join("Kurs");
+        assert!(rules.is_ignored(&file("Kurs/code.zip"), &course));
+        assert!(!rules.is_ignored(&folder("Kurs/code.zip"), &course));
+        assert!(rules.is_ignored(&node("Forum", IlNodeType::Forum), &course));
+        assert!(!rules.is_ignored(&node("Übung", IlNodeType::Exercise), &course));
+    }
+
+    #[test]
+    fn course_rules_stay_in_their_course() {
+        let rules = rules("", "*.pdf\n!Skript.pdf\n");
+        let root = Path::new(ROOT_PATH);
+        assert!(rules.is_ignored(&file("Kurs/Blatt1.pdf"), root));
+        assert!(!rules.is_ignored(&file("Kurs/Skript.pdf"), root));
+        assert!(!rules.is_ignored(&file("Anderer_Kurs/Blatt1.pdf"), root));
+        // the course itself can't be ignored by its own rules
+        assert!(!rules.is_ignored(&folder("Kurs"), root));
+    }
+}
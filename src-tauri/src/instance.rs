@@ -0,0 +1,82 @@
+use crate::ilias::ILIAS_ROOT;
+use dirs::config_dir;
+use log::warn;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// How the client gets a session for an instance
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum LoginStrategy {
+    /// Plain http SAML flow, falling back to the browser if it fails
+    Shibboleth,
+    /// Remote controlled headless chrome
+    Browser,
+}
+
+/// Everything that differs between two ILIAS installations
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IliasInstance {
+    pub name: String,
+    /// Url every relative ilias-link is resolved against, e.g. `https://ilias.uni-freiburg.de/`
+    pub base_url: String,
+    /// Page that starts the login flow
+    pub login_url: String,
+    pub login_strategy: LoginStrategy,
+    /// Relative uri of the page listing all courses of the user
+    pub root_uri: String,
+}
+
+impl Default for IliasInstance {
+    fn default() -> Self {
+        Self {
+            name: "Uni Freiburg".to_string(),
+            base_url: "https://ilias.uni-freiburg.de/".to_string(),
+            login_url: "https://ilias.uni-freiburg.de/shib_login.php?target=".to_string(),
+            login_strategy: LoginStrategy::Shibboleth,
+            root_uri: ILIAS_ROOT.to_string(),
+        }
+    }
+}
+
+fn instance_path() -> Option<PathBuf> {
+    config_dir().map(|mut path| {
+        path.push("better-ilias/instance.json");
+        path
+    })
+}
+
+impl IliasInstance {
+    /// Loads the profile from the config dir or falls back to uni-freiburg
+    pub fn load() -> Self {
+        let path = match instance_path() {
+            Some(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str(&data)?))
+        {
+            Ok(instance) => instance,
+            Err(err) => {
+                warn!("can't load instance from {path:?}, using default: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Whether `url` points to this installation, so that the session may be sent along
+    pub fn is_own(&self, url: &str) -> bool {
+        let host = |url: &str| Url::parse(url).ok()?.host_str().map(str::to_string);
+        host(url).is_some_and(|host_of_url| Some(host_of_url) == host(&self.base_url))
+    }
+
+    /// Turns a (possibly relative) ilias-link into an absolute url
+    pub fn url(&self, uri: &str) -> String {
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            return uri.to_string();
+        }
+        let base = self.base_url.trim_end_matches('/');
+        format!("{base}/{}", uri.trim_start_matches('/'))
+    }
+}
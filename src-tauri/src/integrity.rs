@@ -0,0 +1,107 @@
+use crate::ilias::{walk, FileMeta, IlNodeType, WrappedNode};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How a local file differs from what was downloaded
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub enum Problem {
+    Missing,
+    Truncated { expected: u64, found: u64 },
+    Modified,
+    Unreadable(String),
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct IntegrityIssue {
+    pub uri: String,
+    pub title: String,
+    pub path: PathBuf,
+    pub problem: Problem,
+}
+
+/// Hex encoded SHA-256 and size of the file at `path`
+pub fn hash_file(path: &Path) -> io::Result<(String, u64)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+        size += len as u64;
+    }
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+/// Fingerprint of the file at `path` as if it was downloaded just now
+pub fn fingerprint(path: &Path) -> io::Result<FileMeta> {
+    let (sha256, size) = hash_file(path)?;
+    let downloaded_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    Ok(FileMeta {
+        sha256,
+        size,
+        downloaded_at,
+    })
+}
+
+/// Compares the file at `path` with the fingerprint taken when it was downloaded
+pub fn check(path: &Path, meta: &FileMeta) -> Option<Problem> {
+    if !path.exists() {
+        return Some(Problem::Missing);
+    }
+    match hash_file(path) {
+        Ok((sha256, _)) if sha256 == meta.sha256 => None,
+        Ok((_, found)) if found < meta.size => Some(Problem::Truncated {
+            expected: meta.size,
+            found,
+        }),
+        Ok(_) => Some(Problem::Modified),
+        Err(err) => Some(Problem::Unreadable(err.to_string())),
+    }
+}
+
+/// Rehashes every downloaded file below `root`
+pub fn verify(root: &WrappedNode) -> Vec<IntegrityIssue> {
+    let mut files = vec![];
+    walk(root, &mut |_, node| {
+        if let IlNodeType::File {
+            path,
+            meta: Some(meta),
+            ..
+        } = &node.breed
+        {
+            files.push((
+                node.uri.clone(),
+                node.title.clone(),
+                path.clone(),
+                meta.clone(),
+            ));
+        }
+    });
+
+    // hash without holding any locks on the tree
+    files
+        .into_iter()
+        .filter_map(|(uri, title, path, meta)| {
+            let problem = check(&path, &meta)?;
+            Some(IntegrityIssue {
+                uri,
+                title,
+                path,
+                problem,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,173 @@
+use crate::ilias::{IlNodeType, WrappedNode};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// What the sync does with the files in a course or folder.
+/// Folders without their own policy inherit the one of their parent.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SyncPolicy {
+    /// Download new files and new versions
+    #[default]
+    DownloadAll,
+    /// Track files and their versions without downloading them
+    MetadataOnly,
+    /// Don't crawl at all
+    Never,
+}
+
+impl SyncPolicy {
+    pub fn downloads(self) -> bool {
+        self == SyncPolicy::DownloadAll
+    }
+}
+
+/// The policy `breed` sets for its children, given the one it inherited
+pub fn effective(breed: &IlNodeType, inherited: SyncPolicy) -> SyncPolicy {
+    match breed {
+        IlNodeType::Folder {
+            policy: Some(policy),
+            ..
+        } => *policy,
+        _ => inherited,
+    }
+}
+
+/// The policy a node below `ancestors`, listed from the root down, inherits
+pub fn inherited(ancestors: &[WrappedNode]) -> SyncPolicy {
+    ancestors
+        .iter()
+        .fold(SyncPolicy::default(), |policy, node| {
+            effective(&node.lock().unwrap().breed, policy)
+        })
+}
+
+/// Marks which files below `node` should be stored locally under `policy`
+fn mark_files(node: &WrappedNode, policy: SyncPolicy) {
+    let children = {
+        let mut guard = node.lock().unwrap();
+        if let IlNodeType::File {
+            local, over_limit, ..
+        } = &mut guard.breed
+        {
+            *local = policy.downloads();
+            // the limits are checked again by the next sync
+            *over_limit = false;
+        }
+        guard.children.clone().unwrap_or_default()
+    };
+    let policy = effective(&node.lock().unwrap().breed, policy);
+    for child in &children {
+        mark_files(child, policy);
+    }
+}
+
+/// Sets the policy of the course or folder `node`, `None` to inherit it again.
+/// Files below it that are now downloaded get fetched by the next sync.
+pub fn set(node: &WrappedNode, inherited: SyncPolicy, policy: Option<SyncPolicy>) -> Result<()> {
+    match &mut node.lock().unwrap().breed {
+        IlNodeType::Folder {
+            policy: current, ..
+        } => *current = policy,
+        _ => return Err(anyhow!("only courses and folders have a sync policy")),
+    }
+    mark_files(node, inherited);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ilias::IlNode;
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    fn folder(policy: Option<SyncPolicy>, children: Vec<WrappedNode>) -> WrappedNode {
+        Arc::new(Mutex::new(IlNode {
+            breed: IlNodeType::Folder {
+                path: PathBuf::new(),
+                policy,
+            },
+            children: Some(children),
+            ..Default::default()
+        }))
+    }
+
+    fn file() -> WrappedNode {
+        Arc::new(Mutex::new(IlNode {
+            breed: IlNodeType::File {
+                path: PathBuf::new(),
+                version: 1,
+                local: false,
+                original_name: None,
+                meta: None,
+                conflict: None,
+                size: None,
+                over_limit: false,
+            },
+            children: None,
+            ..Default::default()
+        }))
+    }
+
+    fn local(node: &WrappedNode) -> bool {
+        *node.lock().unwrap().breed.get_local().unwrap()
+    }
+
+    #[test]
+    fn folders_override_the_inherited_policy() {
+        let own = IlNodeType::Folder {
+            path: PathBuf::new(),
+            policy: Some(SyncPolicy::MetadataOnly),
+        };
+        let inheriting = IlNodeType::Folder {
+            path: PathBuf::new(),
+            policy: None,
+        };
+        assert_eq!(effective(&own, SyncPolicy::Never), SyncPolicy::MetadataOnly);
+        assert_eq!(effective(&inheriting, SyncPolicy::Never), SyncPolicy::Never);
+        assert_eq!(
+            effective(&IlNodeType::Forum, SyncPolicy::MetadataOnly),
+            SyncPolicy::MetadataOnly
+        );
+    }
+
+    #[test]
+    fn the_closest_folder_decides() {
+        let ancestors = [
+            folder(None, vec![]),
+            folder(Some(SyncPolicy::Never), vec![]),
+            folder(Some(SyncPolicy::MetadataOnly), vec![]),
+            folder(None, vec![]),
+        ];
+        assert_eq!(inherited(&ancestors), SyncPolicy::MetadataOnly);
+        assert_eq!(inherited(&[]), SyncPolicy::DownloadAll);
+    }
+
+    #[test]
+    fn setting_a_policy_marks_the_files_below() {
+        let (direct, nested) = (file(), file());
+        let inner = folder(None, vec![nested.clone()]);
+        let course = folder(None, vec![direct.clone(), inner.clone()]);
+
+        set(
+            &inner,
+            SyncPolicy::DownloadAll,
+            Some(SyncPolicy::MetadataOnly),
+        )
+        .unwrap();
+        set(
+            &course,
+            SyncPolicy::DownloadAll,
+            Some(SyncPolicy::DownloadAll),
+        )
+        .unwrap();
+        assert!(local(&direct));
+        assert!(!local(&nested));
+
+        set(&inner, SyncPolicy::DownloadAll, None).unwrap();
+        assert!(local(&nested));
+        assert!(set(&direct, SyncPolicy::DownloadAll, None).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+/// Download progress is only reported after this many new bytes, to not flood the frontend
+const REPORT_EVERY: u64 = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum ProgressEvent {
+    Started,
+    Crawl {
+        crawled: usize,
+        remaining: usize,
+    },
+    Download {
+        uri: String,
+        path: PathBuf,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    FileDone {
+        uri: String,
+        path: PathBuf,
+    },
+    FileFailed {
+        uri: String,
+        error: String,
+    },
+    Finished {
+        completed: usize,
+        failed: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileProgress {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub total: Option<u64>,
+    #[serde(skip)]
+    reported: u64,
+}
+
+/// Snapshot of the running (or last) sync
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct SyncStatus {
+    pub running: bool,
+    pub containers_crawled: usize,
+    pub containers_remaining: usize,
+    pub files_completed: usize,
+    pub files_failed: usize,
+    /// Downloads in flight by uri
+    pub downloads: HashMap<String, FileProgress>,
+}
+
+type Listener = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Collects the progress of a sync and forwards every change to a listener
+#[derive(Default)]
+pub struct Progress {
+    status: Mutex<SyncStatus>,
+    listener: RwLock<Option<Listener>>,
+}
+
+impl Progress {
+    pub fn set_listener(&self, listener: impl Fn(&ProgressEvent) + Send + Sync + 'static) {
+        *self.listener.write().unwrap() = Some(Box::new(listener));
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        if let Some(listener) = &*self.listener.read().unwrap() {
+            listener(&event);
+        }
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn started(&self) {
+        *self.status.lock().unwrap() = SyncStatus {
+            running: true,
+            ..Default::default()
+        };
+        self.emit(ProgressEvent::Started);
+    }
+
+    pub fn finished(&self) {
+        let event = {
+            let mut status = self.status.lock().unwrap();
+            status.running = false;
+            ProgressEvent::Finished {
+                completed: status.files_completed,
+                failed: status.files_failed,
+            }
+        };
+        self.emit(event);
+    }
+
+    /// A container (course or folder) waits to be crawled
+    pub fn container_queued(&self) {
+        let event = {
+            let mut status = self.status.lock().unwrap();
+            status.containers_remaining += 1;
+            crawl_event(&status)
+        };
+        self.emit(event);
+    }
+
+    pub fn container_crawled(&self) {
+        let event = {
+            let mut status = self.status.lock().unwrap();
+            status.containers_remaining = status.containers_remaining.saturating_sub(1);
+            status.containers_crawled += 1;
+            crawl_event(&status)
+        };
+        self.emit(event);
+    }
+
+    /// `bytes` of the file at `uri` are on disk
+    pub fn file_bytes(&self, uri: &str, path: &Path, bytes: u64, total: Option<u64>) {
+        let event = {
+            let mut status = self.status.lock().unwrap();
+            let file = status
+                .downloads
+                .entry(uri.to_string())
+                .or_insert_with(|| FileProgress {
+                    path: path.to_path_buf(),
+                    bytes: 0,
+                    total,
+                    reported: 0,
+                });
+            file.bytes = bytes;
+            file.total = total;
+            let first = file.reported == 0;
+            let done = total.is_some_and(|total| bytes >= total);
+            if first || done || bytes.saturating_sub(file.reported) >= REPORT_EVERY {
+                file.reported = bytes.max(1);
+                Some(ProgressEvent::Download {
+                    uri: uri.to_string(),
+                    path: path.to_path_buf(),
+                    bytes,
+                    total,
+                })
+            } else {
+                None
+            }
+        };
+        if let Some(event) = event {
+            self.emit(event);
+        }
+    }
+
+    /// Records the outcome of a download job. Jobs that never started transferring (because the
+    /// file already existed or shouldn't be stored) only count if they failed.
+    pub fn file_finished(&self, uri: &str, result: &anyhow::Result<()>) {
+        let event = {
+            let mut status = self.status.lock().unwrap();
+            let file = status.downloads.remove(uri);
+            match (result, file) {
+                (Err(err), _) => {
+                    status.files_failed += 1;
+                    Some(ProgressEvent::FileFailed {
+                        uri: uri.to_string(),
+                        error: err.to_string(),
+                    })
+                }
+                (Ok(_), Some(file)) => {
+                    status.files_completed += 1;
+                    Some(ProgressEvent::FileDone {
+                        uri: uri.to_string(),
+                        path: file.path,
+                    })
+                }
+                (Ok(_), None) => None,
+            }
+        };
+        if let Some(event) = event {
+            self.emit(event);
+        }
+    }
+}
+
+fn crawl_event(status: &SyncStatus) -> ProgressEvent {
+    ProgressEvent::Crawl {
+        crawled: status.containers_crawled,
+        remaining: status.containers_remaining,
+    }
+}
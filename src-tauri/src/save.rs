@@ -0,0 +1,328 @@
+use crate::{ilias::IlNode, tree::path_segment};
+use anyhow::{anyhow, bail, Context, Result};
+use dirs::cache_dir;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the save format written by this build
+pub const VERSION: u64 = 2;
+
+/// Brings a tree saved with version `n` to version `n + 1`.
+/// Saves without envelope are version 0.
+const MIGRATIONS: [fn(&mut Value); VERSION as usize] = [upgrade_videos_root, drop_store_files];
+
+/// What is written to `save.json`
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    tree: &'a IlNode,
+}
+
+/// Just the version of a save, 0 for saves without envelope
+#[derive(Deserialize)]
+struct SavedVersion {
+    #[serde(default)]
+    version: u64,
+}
+
+fn saves_path() -> Option<PathBuf> {
+    cache_dir().map(|mut path| {
+        path.push("better-ilias/save.json");
+        path
+    })
+}
+
+/// `path` with `suffix` appended to the file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Copy of the previous save, written before it is replaced
+fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+fn upgrade_videos_root(tree: &mut Value) {
+    upgrade_videos(tree, Path::new(""));
+}
+
+/// Saves from before videos could be downloaded store them as the unit variant `"Video"`.
+/// They get the path they would have been given by the crawler.
+fn upgrade_videos(node: &mut Value, parent: &Path) {
+    let title = node["title"].as_str().unwrap_or_default().to_string();
+    if node["breed"] == "Video" {
+        node["breed"] = json!({ "Video": { "path": parent.join(path_segment(&title)) } });
+    }
+    let path = node["breed"]["Folder"]["path"]
+        .as_str()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| parent.to_path_buf());
+    if let Some(children) = node["children"].as_array_mut() {
+        for child in children {
+            upgrade_videos(child, &path);
+        }
+    }
+}
+
+/// Folders had a `store_files` flag that was never read, the sync policy took its place
+fn drop_store_files(node: &mut Value) {
+    let folder = node
+        .get_mut("breed")
+        .and_then(|breed| breed.get_mut("Folder"))
+        .and_then(Value::as_object_mut);
+    if let Some(folder) = folder {
+        folder.remove("store_files");
+    }
+    if let Some(children) = node["children"].as_array_mut() {
+        for child in children {
+            drop_store_files(child);
+        }
+    }
+}
+
+/// Parses a save of any known version and migrates it to the current one
+fn parse(data: &str) -> Result<IlNode> {
+    let save: Value = serde_json::from_str(data)?;
+    let (version, mut tree) = match save {
+        Value::Object(mut envelope) if envelope.contains_key("version") => {
+            let version = envelope["version"]
+                .as_u64()
+                .ok_or(anyhow!("version is not a number"))?;
+            let tree = envelope.remove("tree").ok_or(anyhow!("save has no tree"))?;
+            (version, tree)
+        }
+        tree => (0, tree),
+    };
+    if version > VERSION {
+        bail!("save has version {version}, this build only knows up to {VERSION}");
+    }
+    for migrate in &MIGRATIONS[version as usize..] {
+        migrate(&mut tree);
+    }
+    if version < VERSION {
+        info!("migrated save from version {version} to {VERSION}");
+    }
+    Ok(serde_json::from_value(tree)?)
+}
+
+/// Moves the unreadable save at `path` aside so it isn't overwritten by the next save
+fn quarantine(path: &Path) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let target = sibling(path, &format!(".corrupt-{now}"));
+    match fs::rename(path, &target) {
+        Ok(()) => warn!("moved the unreadable save to {target:?}"),
+        Err(err) => warn!("can't move the unreadable save {path:?} aside: {err}"),
+    }
+}
+
+/// Version of the save at `path` if it was written by a newer build, which this one can't
+/// read and must not replace
+fn newer_version(path: &Path) -> Option<u64> {
+    let data = fs::read_to_string(path).ok()?;
+    let saved = serde_json::from_str::<SavedVersion>(&data).ok()?;
+    (saved.version > VERSION).then_some(saved.version)
+}
+
+fn load_from(path: &Path) -> Option<IlNode> {
+    if !path.exists() {
+        return None;
+    }
+    if let Some(version) = newer_version(path) {
+        warn!("the save {path:?} has version {version}, this build only knows up to {VERSION}, leaving it untouched");
+        return None;
+    }
+    let error = match fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| parse(&data))
+    {
+        Ok(tree) => return Some(tree),
+        Err(err) => err,
+    };
+    warn!("can't load the save {path:?}: {error}");
+    quarantine(path);
+
+    let backup = backup_path(path);
+    match fs::read_to_string(&backup)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| parse(&data))
+    {
+        Ok(tree) => {
+            warn!("restored the tree from the backup {backup:?}");
+            Some(tree)
+        }
+        Err(err) => {
+            if backup.exists() {
+                warn!("can't load the backup {backup:?} either: {err}");
+            }
+            None
+        }
+    }
+}
+
+fn store_to(path: &Path, tree: &IlNode) -> Result<()> {
+    if let Some(version) = newer_version(path) {
+        bail!("won't overwrite the save {path:?} of the newer version {version}");
+    }
+    let data = serde_json::to_string(&Envelope {
+        version: VERSION,
+        tree,
+    })?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    if path.exists() {
+        fs::copy(path, backup_path(path)).context("can't back up the save")?;
+    }
+    // a crash while writing leaves the old save in place
+    let part = sibling(path, ".part");
+    fs::write(&part, data)?;
+    fs::rename(&part, path)?;
+    Ok(())
+}
+
+/// The saved tree. If the save can't be read, it is moved aside and the backup is tried,
+/// then an empty tree is used. A save written by a newer build is left where it is and
+/// [store] refuses to replace it.
+pub fn load() -> IlNode {
+    saves_path()
+        .and_then(|path| load_from(&path))
+        .unwrap_or_default()
+}
+
+/// Saves `tree`, keeping the previous save as a backup
+pub fn store(tree: &IlNode) -> Result<()> {
+    let path = saves_path().ok_or(anyhow!("can't create path"))?;
+    store_to(&path, tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ilias::IlNodeType;
+
+    fn save_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("better-ilias-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::remove_file(&path).ok();
+        fs::remove_file(backup_path(&path)).ok();
+        path
+    }
+
+    fn course(title: &str) -> IlNode {
+        IlNode {
+            uri: format!("goto.php?target=crs_{title}"),
+            title: title.to_string(),
+            breed: IlNodeType::Group,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn migrates_saves_without_envelope() {
+        let save = json!({
+            "uri": "root",
+            "title": "Root",
+            "breed": "Root",
+            "visible": true,
+            "children": [{
+                "uri": "crs",
+                "title": "Course",
+                "breed": { "Folder": { "store_files": true, "path": "studium/Course" } },
+                "visible": true,
+                "children": [{
+                    "uri": "video",
+                    "title": "Lecture 1",
+                    "breed": "Video",
+                    "visible": true,
+                    "children": []
+                }]
+            }]
+        });
+        let tree = parse(&save.to_string()).unwrap();
+        let course = tree.children.unwrap()[0].lock().unwrap().clone();
+        let video = course.children.unwrap()[0].lock().unwrap().clone();
+        assert_eq!(
+            video.breed.local_path(),
+            Some(PathBuf::from("studium/Course/Lecture_1"))
+        );
+        assert_eq!(
+            course.breed,
+            IlNodeType::Folder {
+                path: PathBuf::from("studium/Course"),
+                policy: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_saves_from_newer_versions() {
+        let save = json!({ "version": VERSION + 1, "tree": IlNode::default() });
+        assert!(parse(&save.to_string()).is_err());
+    }
+
+    #[test]
+    fn leaves_saves_from_newer_versions_alone() {
+        let path = save_path("newer.json");
+        let data = json!({ "version": VERSION + 1, "tree": course("newer") }).to_string();
+        fs::write(&path, &data).unwrap();
+
+        assert!(load_from(&path).is_none());
+        assert!(store_to(&path, &course("older")).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), data);
+        assert!(!backup_path(&path).exists());
+    }
+
+    #[test]
+    fn round_trips_and_keeps_a_backup() {
+        let path = save_path("round_trip.json");
+        store_to(&path, &course("first")).unwrap();
+        store_to(&path, &course("second")).unwrap();
+
+        assert_eq!(load_from(&path).unwrap().title, "second");
+        assert_eq!(
+            parse(&fs::read_to_string(backup_path(&path)).unwrap())
+                .unwrap()
+                .title,
+            "first"
+        );
+    }
+
+    #[test]
+    fn quarantines_corrupt_saves_and_falls_back_to_the_backup() {
+        let path = save_path("corrupt.json");
+        store_to(&path, &course("first")).unwrap();
+        store_to(&path, &course("second")).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+
+        assert_eq!(load_from(&path).unwrap().title, "first");
+        assert!(!path.exists());
+        let quarantined = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("corrupt.json.corrupt-")
+            });
+        assert!(quarantined);
+    }
+
+    #[test]
+    fn starts_empty_without_a_readable_save() {
+        let path = save_path("garbage.json");
+        fs::write(&path, "not json").unwrap();
+        assert!(load_from(&path).is_none());
+    }
+}
@@ -0,0 +1,595 @@
+use crate::{
+    arena::NodeId,
+    ilias::{IlNodeType, WrappedNode},
+};
+use anyhow::{anyhow, Result};
+use dirs::cache_dir;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, panic,
+    path::{Path, PathBuf},
+};
+
+/// Hits returned when no limit is given
+pub const DEFAULT_LIMIT: usize = 20;
+/// Text files bigger than this are only indexed by their title
+const MAX_TEXT_FILE: u64 = 4 << 20;
+/// Characters shown before and after the match in a snippet
+const SNIPPET_CONTEXT: usize = 60;
+/// Extensions of files indexed as plain text
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "tex", "csv", "json", "ipynb", "html", "xml", "py", "java", "c", "h", "cpp", "hs",
+    "rs", "js", "sql", "m", "r",
+];
+
+/// How much a match in each field counts
+const TITLE_WEIGHT: f64 = 5.0;
+const DESCRIPTION_WEIGHT: f64 = 2.0;
+const LOCATION_WEIGHT: f64 = 1.0;
+const CONTENT_WEIGHT: f64 = 1.0;
+
+/// What is known about one node
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq)]
+struct Document {
+    #[serde(default)]
+    id: NodeId,
+    title: String,
+    /// Titles from the course down to the parent of the node
+    location: String,
+    #[serde(default)]
+    description: Option<String>,
+    /// Text extracted from the downloaded file
+    #[serde(default)]
+    content: String,
+    /// SHA-256 of the file `content` was extracted from
+    #[serde(default)]
+    sha256: Option<String>,
+}
+
+impl Document {
+    /// Every term of the document with its weighted number of occurrences
+    fn terms(&self) -> HashMap<String, f64> {
+        let mut terms = HashMap::new();
+        let fields = [
+            (self.title.as_str(), TITLE_WEIGHT),
+            (
+                self.description.as_deref().unwrap_or_default(),
+                DESCRIPTION_WEIGHT,
+            ),
+            (self.location.as_str(), LOCATION_WEIGHT),
+            (self.content.as_str(), CONTENT_WEIGHT),
+        ];
+        for (text, weight) in fields {
+            for term in tokens(text) {
+                *terms.entry(term).or_default() += weight;
+            }
+        }
+        terms
+    }
+}
+
+/// A node matching a query
+#[derive(Debug, Serialize, Clone)]
+pub struct SearchHit {
+    pub id: NodeId,
+    pub uri: String,
+    pub title: String,
+    /// Titles from the course down to the parent of the node
+    pub location: String,
+    pub score: f64,
+    /// Text around the first match in the description or the file
+    pub snippet: Option<String>,
+}
+
+/// Full-text index over the titles and descriptions of the nodes and the text of
+/// downloaded documents, kept in `search.json` next to the save
+#[derive(Deserialize, Serialize, Default)]
+pub struct SearchIndex {
+    /// By uri of the node
+    documents: HashMap<String, Document>,
+    /// Term to uri and weighted occurrences, rebuilt from `documents` on load
+    #[serde(skip)]
+    terms: HashMap<String, HashMap<String, f64>>,
+}
+
+fn index_path() -> Option<PathBuf> {
+    cache_dir().map(|mut path| {
+        path.push("better-ilias/search.json");
+        path
+    })
+}
+
+/// Words of `text` with their byte offset
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (index, character) in text.char_indices() {
+        match (character.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(from)) => {
+                words.push((from, &text[from..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        words.push((from, &text[from..]));
+    }
+    words
+}
+
+/// The lowercased words of `text` that are long enough to search for
+fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+    words(text)
+        .into_iter()
+        .map(|(_, word)| word.to_lowercase())
+        .filter(|word| word.chars().count() > 1)
+}
+
+/// Reads the text of the downloaded file at `path`, `None` for files that aren't documents
+fn extract(path: &Path) -> Option<Result<String>> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    if extension == "pdf" {
+        let path = path.to_path_buf();
+        // the pdf parser panics on some malformed files instead of failing
+        let result = panic::catch_unwind(|| pdf_extract::extract_text(&path))
+            .map_err(|_| anyhow!("can't parse pdf"))
+            .and_then(|result| result.map_err(|err| anyhow!("{err}")));
+        return Some(result);
+    }
+    if !TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    let result = fs::metadata(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|meta| {
+            if meta.len() > MAX_TEXT_FILE {
+                return Ok(String::new());
+            }
+            Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
+        });
+    Some(result)
+}
+
+/// About [SNIPPET_CONTEXT] characters around the first word of `text` starting with a query term
+fn snippet(text: &str, query: &[String]) -> Option<String> {
+    let (start, word) = words(text).into_iter().find(|(_, word)| {
+        let word = word.to_lowercase();
+        query.iter().any(|term| word.starts_with(term.as_str()))
+    })?;
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map(|(index, _)| index);
+    let end = start + word.len();
+    let to = text[end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map(|(index, _)| end + index);
+    let context = text[from.unwrap_or(0)..to.unwrap_or(text.len())]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(format!(
+        "{}{context}{}",
+        if from.is_some() { "…" } else { "" },
+        if to.is_some() { "…" } else { "" },
+    ))
+}
+
+/// The score of every uri for `query_term` from the index `terms` it matches,
+/// out of `documents` in the index
+fn scores<'a>(
+    query_term: &str,
+    terms: Vec<(&String, &'a HashMap<String, f64>)>,
+    documents: f64,
+) -> HashMap<&'a str, f64> {
+    let mut scores = HashMap::<&str, f64>::new();
+    for (term, uris) in terms {
+        let idf = (1.0 + documents / uris.len() as f64).ln();
+        // words merely starting with the term count less
+        let exact = if term == query_term { 1.0 } else { 0.5 };
+        for (uri, weight) in uris {
+            let score = idf * exact * weight * 2.2 / (weight + 1.2);
+            let entry = scores.entry(uri.as_str()).or_default();
+            *entry = entry.max(score);
+        }
+    }
+    scores
+}
+
+/// A node below the root as it should be indexed
+struct Entry {
+    id: NodeId,
+    uri: String,
+    title: String,
+    location: String,
+    description: Option<String>,
+    /// Path and SHA-256 of the downloaded file
+    download: Option<(PathBuf, String)>,
+    /// Text read from the download, `None` if the indexed text is still current
+    text: Option<String>,
+}
+
+/// The nodes below the root with the text of the files that changed, read by
+/// [SearchIndex::read] and put into the index by [SearchIndex::apply]
+pub struct Pending(Vec<Entry>);
+
+fn entries(root: &WrappedNode) -> Vec<Entry> {
+    fn visit(node: &WrappedNode, titles: &[String], entries: &mut Vec<Entry>) {
+        let node = node.lock().unwrap();
+        let download = match &node.breed {
+            IlNodeType::File {
+                path,
+                meta: Some(meta),
+                ..
+            } => Some((path.clone(), meta.sha256.clone())),
+            _ => None,
+        };
+        let mut titles = titles.to_vec();
+        if node.breed != IlNodeType::Root {
+            entries.push(Entry {
+                id: node.id,
+                uri: node.uri.clone(),
+                title: node.title.clone(),
+                location: titles.join(" / "),
+                description: node.description.clone(),
+                download,
+                text: None,
+            });
+            titles.push(node.title.clone());
+        }
+        for child in node.children.iter().flatten() {
+            visit(child, &titles, entries);
+        }
+    }
+    let mut entries = vec![];
+    visit(root, &[], &mut entries);
+    entries
+}
+
+impl SearchIndex {
+    pub fn load() -> Self {
+        let Some(path) = index_path().filter(|path| path.exists()) else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str::<Self>(&data)?))
+        {
+            Ok(mut index) => {
+                let uris = index.documents.keys().cloned().collect::<Vec<_>>();
+                for uri in uris {
+                    index.add_terms(&uri);
+                }
+                index
+            }
+            Err(err) => {
+                warn!("can't load search index from {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = index_path().ok_or(anyhow!("can't create path"))?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn add_terms(&mut self, uri: &str) {
+        let Some(document) = self.documents.get(uri) else {
+            return;
+        };
+        for (term, weight) in document.terms() {
+            self.terms
+                .entry(term)
+                .or_default()
+                .insert(uri.to_string(), weight);
+        }
+    }
+
+    fn remove(&mut self, uri: &str) {
+        let Some(document) = self.documents.remove(uri) else {
+            return;
+        };
+        for term in document.terms().into_keys() {
+            if let Some(uris) = self.terms.get_mut(&term) {
+                uris.remove(uri);
+                if uris.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// SHA-256 of the file each document was read from, by uri
+    pub fn hashes(&self) -> HashMap<String, Option<String>> {
+        self.documents
+            .iter()
+            .map(|(uri, document)| (uri.clone(), document.sha256.clone()))
+            .collect()
+    }
+
+    /// The nodes below `root` with the text of the files downloaded or changed since the
+    /// index had the `known` [hashes](Self::hashes). Reading the files takes a while, so
+    /// this doesn't need the index and searches keep working meanwhile.
+    pub fn read(root: &WrappedNode, known: &HashMap<String, Option<String>>) -> Pending {
+        let mut entries = entries(root);
+        for entry in &mut entries {
+            let Some((path, sha256)) = &entry.download else {
+                continue;
+            };
+            if known.get(&entry.uri) == Some(&Some(sha256.clone())) {
+                continue;
+            }
+            entry.text = Some(match extract(path) {
+                Some(Ok(text)) => text.split_whitespace().collect::<Vec<_>>().join(" "),
+                Some(Err(err)) => {
+                    // remembered with the hash so the file isn't tried on every sync
+                    warn!("can't extract text from {path:?}: {err}");
+                    String::new()
+                }
+                None => String::new(),
+            });
+        }
+        Pending(entries)
+    }
+
+    /// Brings the index in line with the tree [read](Self::read) before. Removed nodes
+    /// are dropped. Returns how many documents changed.
+    pub fn apply(&mut self, Pending(entries): Pending) -> usize {
+        let listed = entries
+            .iter()
+            .map(|entry| entry.uri.clone())
+            .collect::<HashSet<_>>();
+        let removed = self
+            .documents
+            .keys()
+            .filter(|uri| !listed.contains(*uri))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut changed = removed.len();
+        for uri in removed {
+            self.remove(&uri);
+        }
+
+        for entry in entries {
+            let known = self.documents.get(&entry.uri);
+            let sha256 = entry.download.map(|(_, sha256)| sha256);
+            let content = entry
+                .text
+                .or_else(|| {
+                    known
+                        .filter(|known| known.sha256 == sha256)
+                        .map(|known| known.content.clone())
+                })
+                .unwrap_or_default();
+            let document = Document {
+                id: entry.id,
+                title: entry.title,
+                location: entry.location,
+                description: entry.description,
+                content,
+                sha256,
+            };
+            if known == Some(&document) {
+                continue;
+            }
+            changed += 1;
+            self.remove(&entry.uri);
+            self.documents.insert(entry.uri.clone(), document);
+            self.add_terms(&entry.uri);
+        }
+        changed
+    }
+
+    /// Brings the index in line with the tree below `root`. Only files downloaded or
+    /// changed since the last update are read again. Returns how many documents changed.
+    pub fn update(&mut self, root: &WrappedNode) -> usize {
+        let pending = Self::read(root, &self.hashes());
+        self.apply(pending)
+    }
+
+    /// The nodes containing every word of `query`, best matches first. The last word may be
+    /// the beginning of a word, so results show up while typing.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query = tokens(query).collect::<Vec<_>>();
+        let Some((last, rest)) = query.split_last() else {
+            return vec![];
+        };
+        let documents = self.documents.len() as f64;
+        let mut per_term = rest
+            .iter()
+            .map(|term| {
+                let terms = self.terms.get_key_value(term).into_iter().collect();
+                scores(term, terms, documents)
+            })
+            .collect::<Vec<_>>();
+        let completions = self
+            .terms
+            .iter()
+            .filter(|(term, _)| term.starts_with(last.as_str()))
+            .collect();
+        per_term.push(scores(last, completions, documents));
+
+        let mut hits = per_term[0]
+            .keys()
+            .filter_map(|uri| {
+                let score = per_term
+                    .iter()
+                    .map(|scores| scores.get(uri))
+                    .sum::<Option<f64>>()?;
+                let document = &self.documents[*uri];
+                Some(SearchHit {
+                    id: document.id,
+                    uri: uri.to_string(),
+                    title: document.title.clone(),
+                    location: document.location.clone(),
+                    score,
+                    snippet: snippet(&document.content, &query).or_else(|| {
+                        snippet(document.description.as_deref().unwrap_or_default(), &query)
+                    }),
+                })
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.title.cmp(&b.title)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ilias::{FileMeta, IlNode};
+    use std::sync::{Arc, Mutex};
+
+    fn node(uri: &str, title: &str, breed: IlNodeType, children: Vec<WrappedNode>) -> WrappedNode {
+        Arc::new(Mutex::new(IlNode {
+            uri: uri.to_string(),
+            title: title.to_string(),
+            breed,
+            children: Some(children),
+            ..Default::default()
+        }))
+    }
+
+    fn folder(uri: &str, title: &str, children: Vec<WrappedNode>) -> WrappedNode {
+        let breed = IlNodeType::Folder {
+            path: PathBuf::from(title),
+            policy: None,
+        };
+        node(uri, title, breed, children)
+    }
+
+    fn file(uri: &str, title: &str, path: &Path, sha256: &str) -> WrappedNode {
+        let breed = IlNodeType::File {
+            path: path.to_path_buf(),
+            version: 0,
+            local: true,
+            original_name: None,
+            meta: Some(FileMeta {
+                sha256: sha256.to_string(),
+                size: 0,
+                downloaded_at: 0,
+            }),
+            conflict: None,
+            size: None,
+            over_limit: false,
+        };
+        node(uri, title, breed, vec![])
+    }
+
+    fn titles(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.title.as_str()).collect()
+    }
+
+    #[test]
+    fn tokens_are_lowercased_words() {
+        assert_eq!(
+            tokens("Übung 3: Lösung_v2 (a) für Analysis-II").collect::<Vec<_>>(),
+            ["übung", "lösung", "v2", "für", "analysis", "ii"]
+        );
+    }
+
+    #[test]
+    fn snippets_show_the_text_around_the_match() {
+        let query = ["lemma".to_string()];
+        assert_eq!(
+            snippet("Nach  Lemma 3\ngilt", &query).as_deref(),
+            Some("Nach Lemma 3 gilt")
+        );
+        let text = format!("{} Lemmata {}", "ä".repeat(100), "b".repeat(100));
+        let around = snippet(&text, &query).unwrap();
+        assert!(around.starts_with('…') && around.ends_with('…'));
+        assert!(around.contains("Lemmata"));
+        assert_eq!(
+            around.chars().count(),
+            2 * SNIPPET_CONTEXT + "Lemmata".len() + 2
+        );
+        assert_eq!(snippet("Satz 2", &query), None);
+    }
+
+    #[test]
+    fn ranks_titles_first_and_words_over_beginnings() {
+        let root = node(
+            "",
+            "root",
+            IlNodeType::Root,
+            vec![folder(
+                "course",
+                "Analysis",
+                vec![
+                    folder("a", "Graphentheorie", vec![]),
+                    folder("b", "Skript", vec![]),
+                    folder("c", "Graph", vec![]),
+                    folder("d", "Zusammenfassung", vec![]),
+                ],
+            )],
+        );
+        root.lock().unwrap().children.as_ref().unwrap()[0]
+            .lock()
+            .unwrap()
+            .children
+            .as_ref()
+            .unwrap()[1]
+            .lock()
+            .unwrap()
+            .description = Some("Kapitel über Graph und Baum".to_string());
+        let mut index = SearchIndex::default();
+        index.update(&root);
+
+        assert_eq!(
+            titles(&index.search("graph", 10)),
+            ["Graph", "Skript", "Graphentheorie"]
+        );
+        assert_eq!(titles(&index.search("graph baum", 10)), ["Skript"]);
+        assert_eq!(
+            titles(&index.search("analysis zus", 10)),
+            ["Zusammenfassung"]
+        );
+        assert_eq!(
+            index.search("graph baum", 10)[0].snippet.as_deref(),
+            Some("Kapitel über Graph und Baum")
+        );
+        assert_eq!(index.search("graph", 1).len(), 1);
+        assert!(index.search("", 10).is_empty());
+    }
+
+    #[test]
+    fn updates_only_what_changed() {
+        let path =
+            std::env::temp_dir().join(format!("better-ilias-search-{}.txt", std::process::id()));
+        fs::write(&path, "Eigenwerte und Eigenvektoren").unwrap();
+        let sheet = file("sheet", "Blatt 1", &path, "one");
+        let notes = folder("notes", "Notizen", vec![]);
+        let root = node("", "root", IlNodeType::Root, vec![sheet.clone(), notes]);
+        let mut index = SearchIndex::default();
+        assert_eq!(index.update(&root), 2);
+        assert_eq!(titles(&index.search("eigenvektoren", 10)), ["Blatt 1"]);
+        assert_eq!(index.update(&root), 0);
+
+        // the file is only read again once its hash changed
+        fs::write(&path, "Determinanten").unwrap();
+        assert_eq!(index.update(&root), 0);
+        assert!(index.search("determinanten", 10).is_empty());
+        if let IlNodeType::File { meta, .. } = &mut sheet.lock().unwrap().breed {
+            meta.as_mut().unwrap().sha256 = "two".to_string();
+        }
+        assert_eq!(index.update(&root), 1);
+        assert_eq!(titles(&index.search("determinanten", 10)), ["Blatt 1"]);
+        assert!(index.search("eigenvektoren", 10).is_empty());
+
+        root.lock().unwrap().children.as_mut().unwrap().remove(1);
+        assert_eq!(index.update(&root), 1);
+        assert!(index.search("notizen", 10).is_empty());
+        assert!(!index.terms.contains_key("notizen"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use dirs::config_dir;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+/// User tunable behaviour of the sync, stored in `better-ilias/settings.json`.
+/// Missing fields fall back to their defaults.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub downloads: DownloadSettings,
+    pub versions: VersionSettings,
+    pub schedule: ScheduleSettings,
+    pub videos: VideoSettings,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DownloadSettings {
+    /// How many files are downloaded at the same time
+    pub max_concurrent: usize,
+    /// Upper limit for all downloads together in bytes per second
+    pub bandwidth_limit: Option<u64>,
+    /// Larger files are only available remotely until they are downloaded on demand
+    pub max_file_size: Option<u64>,
+    /// If not empty, only files with these extensions are downloaded by the sync
+    pub allow_extensions: Vec<String>,
+    /// Files with these extensions are only downloaded on demand
+    pub deny_extensions: Vec<String>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            bandwidth_limit: None,
+            max_file_size: Some(100 * 1024 * 1024),
+            allow_extensions: vec![],
+            deny_extensions: vec!["mp4".to_string(), "zip".to_string()],
+        }
+    }
+}
+
+impl DownloadSettings {
+    /// Whether the sync may download a file. An unknown size or extension doesn't count
+    /// against the file, it is checked again once the server answered.
+    pub fn allows(&self, extension: Option<&str>, size: Option<u64>) -> bool {
+        let listed = |list: &[String], extension: &str| {
+            list.iter().any(|listed| {
+                listed
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(extension)
+            })
+        };
+        let too_large = size
+            .zip(self.max_file_size)
+            .is_some_and(|(size, max)| size > max);
+        let extension_allowed = extension.is_none_or(|extension| {
+            (self.allow_extensions.is_empty() || listed(&self.allow_extensions, extension))
+                && !listed(&self.deny_extensions, extension)
+        });
+        !too_large && extension_allowed
+    }
+}
+
+/// Where earlier versions of a file are kept
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum VersionLayout {
+    /// `.versions/folie.v3.pdf`
+    Directory,
+    /// `folie.v3.pdf` next to `folie.pdf`
+    Suffix,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct VersionSettings {
+    /// How many earlier versions of a file are kept, 0 to simply overwrite them
+    pub keep: usize,
+    pub layout: VersionLayout,
+}
+
+impl Default for VersionSettings {
+    fn default() -> Self {
+        Self {
+            keep: 3,
+            layout: VersionLayout::Directory,
+        }
+    }
+}
+
+/// Which rendition of a recording is downloaded
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum Quality {
+    #[default]
+    Highest,
+    Lowest,
+    /// The highest one with at most this many lines, or the lowest if all are higher
+    AtMost(u32),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct VideoSettings {
+    /// Download new recordings during the sync instead of only on demand
+    pub download: bool,
+    pub quality: Quality,
+}
+
+/// When the daemon syncs a course
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum Schedule {
+    /// Every this many minutes
+    Interval(u64),
+    /// A cron pattern like `0 8 * * 1-5`, in local time
+    Cron(String),
+    /// Only when asked to
+    Never,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ScheduleSettings {
+    /// Used for every course without its own schedule
+    pub default: Schedule,
+    /// Schedules of single courses by their title or uri
+    pub courses: HashMap<String, Schedule>,
+}
+
+impl Default for ScheduleSettings {
+    fn default() -> Self {
+        Self {
+            default: Schedule::Interval(60),
+            courses: HashMap::new(),
+        }
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|mut path| {
+        path.push("better-ilias/settings.json");
+        path
+    })
+}
+
+impl Settings {
+    /// Loads the settings from the config dir or falls back to the defaults
+    pub fn load() -> Self {
+        let path = match settings_path() {
+            Some(path) if path.exists() => path,
+            _ => return Self::default(),
+        };
+        match fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str(&data)?))
+        {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("can't load settings from {path:?}, using defaults: {err}");
+                Self::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! A local http server standing in for ilias in tests
+
+use crate::{
+    arena::NodeArena, client::IliasClient, download_queue::DownloadQueue, forum::ForumStore,
+    iliasignore::IgnoreRules, instance::IliasInstance, progress::Progress, settings::Settings,
+    tree::SyncContext,
+};
+use std::sync::{Arc, Mutex};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// What the stub answers to a request
+pub enum Reply {
+    Page(String),
+    /// Redirect to a uri relative to the stub
+    Redirect(String),
+    Status(u16),
+}
+
+/// Answers every request with `answer(request line)` and records the request lines.
+/// Returns a client with a session for the stub.
+pub async fn serve(
+    answer: impl Fn(&str) -> Reply + Send + 'static,
+) -> (IliasClient, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let seen = requests.clone();
+    let base = base_url.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![];
+            let mut chunk = [0; 4096];
+            let head_end = loop {
+                let read = socket.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..read]);
+                if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("content-length: ")?
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or(0);
+            while buf.len() < head_end + length {
+                let read = socket.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..read]);
+            }
+            let line = head.lines().next().unwrap().to_string();
+            let response = match answer(&line) {
+                Reply::Page(page) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{page}",
+                    page.len()
+                ),
+                Reply::Redirect(uri) => format!(
+                    "HTTP/1.1 302 Found\r\nLocation: {base}{uri}\r\n\
+                     Content-Length: 0\r\nConnection: close\r\n\r\n"
+                ),
+                Reply::Status(status) => format!(
+                    "HTTP/1.1 {status} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                ),
+            };
+            seen.lock().unwrap().push(line);
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+        }
+    });
+    let instance = IliasInstance {
+        base_url,
+        ..Default::default()
+    };
+    (IliasClient::with_session(instance, "stub"), requests)
+}
+
+/// A crawl with `client`, the default settings and no ignore rules
+pub fn context(client: IliasClient) -> SyncContext {
+    let settings = Settings::default();
+    SyncContext {
+        client: Arc::new(client),
+        ignore: Arc::new(IgnoreRules::default()),
+        forums: Arc::new(Mutex::new(ForumStore::default())),
+        queue: DownloadQueue::new(&settings.downloads),
+        progress: Arc::new(Progress::default()),
+        settings: Arc::new(settings),
+        arena: Arc::new(Mutex::new(NodeArena::default())),
+    }
+}
+
+/// The page listing the courses `(uri, title)` of the user
+pub fn courses(courses: &[(&str, &str)]) -> String {
+    courses
+        .iter()
+        .map(|(uri, title)| {
+            format!(
+                r#"<div class="il-item"><img class="icon" alt="Kurs">
+                <div class="il-item-title"><a href="{uri}">{title}</a></div></div>"#
+            )
+        })
+        .collect()
+}
+
+/// A container page listing the `(icon, uri, title)` items
+pub fn items(items: &[(&str, &str, &str)]) -> String {
+    items
+        .iter()
+        .map(|(icon, uri, title)| {
+            format!(
+                r#"<div class="ilContainerListItemOuter">
+                <img class="ilListItemIcon" src="templates/icon_{icon}.svg">
+                <div class="il_ContainerItemTitle"><a href="{uri}">{title}</a></div></div>"#
+            )
+        })
+        .collect()
+}
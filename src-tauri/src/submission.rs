@@ -0,0 +1,380 @@
+use lazy_static::lazy_static;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref FORM: Selector = Selector::parse("form").unwrap();
+    static ref FIELDS: Selector = Selector::parse("input[name], textarea[name]").unwrap();
+    static ref FILE_INPUT: Selector = Selector::parse("input[type='file'][name]").unwrap();
+    static ref SUBMIT: Selector =
+        Selector::parse("input[type='submit'][name], button[type='submit'][name]").unwrap();
+    static ref UPLOAD_LINK: Selector = Selector::parse("a[href*='cmd=uploadForm']").unwrap();
+    static ref CONFIRM: Selector = Selector::parse(
+        "input[type='submit'][name^='cmd[confirm'], button[type='submit'][name^='cmd[confirm']"
+    )
+    .unwrap();
+    static ref ERROR: Selector = Selector::parse(".alert-danger").unwrap();
+    static ref NOTICE: Selector = Selector::parse(".alert-info, .alert-warning").unwrap();
+    static ref ROWS: Selector = Selector::parse("tr").unwrap();
+    static ref DELIVERED: Selector = Selector::parse("input[name^='delivered']").unwrap();
+    static ref CELLS: Selector = Selector::parse("td").unwrap();
+}
+
+/// A file ilias lists as handed in for an assignment
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SubmittedFile {
+    pub name: String,
+    /// As shown by ilias
+    pub submitted_at: Option<String>,
+}
+
+/// The multipart form files are uploaded with
+#[derive(Debug, Clone)]
+pub struct UploadForm {
+    pub action: Url,
+    /// Hidden fields and the submit button, ilias checks the form token among them
+    pub fields: Vec<(String, String)>,
+    /// Name of the file input, every file is sent under it
+    pub file_field: String,
+}
+
+/// What the submission page of an assignment offers
+pub enum Upload {
+    Form(UploadForm),
+    /// The form is on a page of its own
+    Link(Url),
+    /// Ilias doesn't accept files, usually because the deadline passed.
+    /// Holds the notice shown instead if there is one.
+    Closed(Option<String>),
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The named fields of `form` that are sent along, without file inputs, buttons and
+/// unchecked boxes
+fn fields(form: &ElementRef) -> Vec<(String, String)> {
+    form.select(&FIELDS)
+        .filter_map(|field| {
+            let element = field.value();
+            let kind = element.attr("type").unwrap_or("text").to_lowercase();
+            let skipped = matches!(
+                kind.as_str(),
+                "file" | "submit" | "button" | "image" | "reset"
+            ) || (matches!(kind.as_str(), "checkbox" | "radio")
+                && element.attr("checked").is_none());
+            if skipped {
+                return None;
+            }
+            let value = match element.name() {
+                "textarea" => field.text().collect(),
+                _ => element.attr("value").unwrap_or_default().to_string(),
+            };
+            Some((element.attr("name")?.to_string(), value))
+        })
+        .collect()
+}
+
+fn button(button: ElementRef) -> Option<(String, String)> {
+    let name = button.value().attr("name")?;
+    let value = button
+        .value()
+        .attr("value")
+        .map(str::to_string)
+        .unwrap_or_else(|| text(button));
+    Some((name.to_string(), value))
+}
+
+fn action(form: &ElementRef, page: &Url) -> Option<Url> {
+    page.join(form.value().attr("action").unwrap_or_default())
+        .ok()
+}
+
+/// The upload form on `html`, which was loaded from `page`
+pub fn upload_form(html: &Html, page: &Url) -> Option<UploadForm> {
+    html.select(&FORM).find_map(|form| {
+        let file_field = form.select(&FILE_INPUT).next()?.value().attr("name")?;
+        let mut fields = fields(&form);
+        // the first submit button is the upload, later ones cancel
+        fields.extend(form.select(&SUBMIT).next().and_then(button));
+        Some(UploadForm {
+            action: action(&form, page)?,
+            fields,
+            file_field: file_field.to_string(),
+        })
+    })
+}
+
+/// Finds out how files are handed in on the submission page `html`, loaded from `page`
+pub fn upload(html: &Html, page: &Url) -> Upload {
+    if let Some(form) = upload_form(html, page) {
+        return Upload::Form(form);
+    }
+    let link = html
+        .select(&UPLOAD_LINK)
+        .find_map(|link| page.join(link.value().attr("href")?).ok());
+    match link {
+        Some(link) => Upload::Link(link),
+        None => Upload::Closed(html.select(&NOTICE).next().map(text)),
+    }
+}
+
+/// A form asking to confirm the upload, as its action and the fields to post
+pub fn confirmation(html: &Html, page: &Url) -> Option<(Url, Vec<(String, String)>)> {
+    html.select(&FORM).find_map(|form| {
+        let confirm = form.select(&CONFIRM).next().and_then(button)?;
+        let mut fields = fields(&form);
+        fields.push(confirm);
+        Some((action(&form, page)?, fields))
+    })
+}
+
+/// The message of a failure ilias shows on `html`
+pub fn error(html: &Html) -> Option<String> {
+    html.select(&ERROR).next().map(text)
+}
+
+/// The files listed as handed in on the submission page `html`
+pub fn submitted_files(html: &Html) -> Vec<SubmittedFile> {
+    html.select(&ROWS)
+        .filter(|row| row.select(&DELIVERED).next().is_some())
+        .filter_map(|row| {
+            // the first cell holds the checkbox, then name and date follow
+            let mut cells = row.select(&CELLS).map(text).filter(|cell| !cell.is_empty());
+            Some(SubmittedFile {
+                name: cells.next()?,
+                submitted_at: cells.next(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::IliasClient, instance::IliasInstance};
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const SUBMISSION_SCREEN: &str = include_str!("../fixtures/submission/submission_screen.html");
+    const UPLOAD_FORM: &str = include_str!("../fixtures/submission/upload_form.html");
+    const CONFIRM: &str = include_str!("../fixtures/submission/confirm.html");
+    const SUBMITTED: &str = include_str!("../fixtures/submission/submitted.html");
+    const CLOSED: &str = include_str!("../fixtures/submission/closed.html");
+    const UPLOAD_ERROR: &str = include_str!("../fixtures/submission/upload_error.html");
+
+    const SUBMIT_URI: &str = "ilias.php?ref_id=2745&ass_id=311&cmd=submissionScreen\
+        &cmdClass=ilexsubmissionfilegui&cmdNode=gn:hu:gq&baseClass=ilexercisehandlergui";
+
+    /// How the stub answers the upload
+    #[derive(Clone, Copy)]
+    enum Answer {
+        /// Redirect back to the submission page
+        Redirect,
+        /// Ask to confirm the submission first
+        Confirm,
+        /// Show an error
+        Error,
+    }
+
+    struct Request {
+        line: String,
+        body: String,
+    }
+
+    /// Replays the recorded pages of a file assignment and records every request
+    async fn serve(closed: bool, answer: Answer) -> (IliasClient, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let seen = requests.clone();
+        let redirect = format!("{base_url}{SUBMIT_URI}");
+        tokio::spawn(async move {
+            let mut submitted = false;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![];
+                let mut chunk = [0; 4096];
+                let head_end = loop {
+                    let read = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..read]);
+                    if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length: ")?
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                while buf.len() < head_end + length {
+                    let read = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..read]);
+                }
+                let line = head.lines().next().unwrap().to_string();
+                let body = String::from_utf8_lossy(&buf[head_end..]).to_string();
+
+                let page = if line.starts_with("GET") && line.contains("cmd=uploadForm") {
+                    Ok(UPLOAD_FORM)
+                } else if line.starts_with("GET") && closed {
+                    Ok(CLOSED)
+                } else if line.starts_with("GET") && submitted {
+                    Ok(SUBMITTED)
+                } else if line.starts_with("GET") {
+                    Ok(SUBMISSION_SCREEN)
+                } else if body.contains("cmd%5BconfirmSubmission%5D") {
+                    submitted = true;
+                    Err(redirect.clone())
+                } else {
+                    match answer {
+                        Answer::Redirect => {
+                            submitted = true;
+                            Err(redirect.clone())
+                        }
+                        Answer::Confirm => Ok(CONFIRM),
+                        Answer::Error => Ok(UPLOAD_ERROR),
+                    }
+                };
+                seen.lock().unwrap().push(Request { line, body });
+
+                let response = match page {
+                    Ok(page) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{page}",
+                        page.len()
+                    ),
+                    Err(location) => format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {location}\r\n\
+                         Content-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+        let instance = IliasInstance {
+            base_url,
+            ..Default::default()
+        };
+        (IliasClient::with_session(instance, "stub"), requests)
+    }
+
+    fn homework(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("better-ilias-submit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn uploads(requests: &[Request]) -> Vec<&Request> {
+        requests
+            .iter()
+            .filter(|request| request.line.contains("fallbackCmd=uploadFile"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn uploads_files_with_form_token() {
+        let (client, requests) = serve(false, Answer::Redirect).await;
+        let files = [
+            homework("loesung.pdf", "%PDF-1.4 loesung"),
+            homework("code.zip", "PK zip"),
+        ];
+
+        let submitted = client.submit_files(SUBMIT_URI, &files).await.unwrap();
+        assert_eq!(
+            submitted,
+            vec![
+                SubmittedFile {
+                    name: "loesung.pdf".to_string(),
+                    submitted_at: Some("18. Okt 2026, 14:02".to_string()),
+                },
+                SubmittedFile {
+                    name: "code.zip".to_string(),
+                    submitted_at: Some("18. Okt 2026, 14:02".to_string()),
+                },
+            ]
+        );
+
+        let requests = requests.lock().unwrap();
+        let uploads = uploads(&requests);
+        assert_eq!(uploads.len(), 1);
+        let upload = uploads[0];
+        assert!(upload.line.starts_with("POST"));
+        assert!(upload.line.contains("rtoken=8f2c1e0b7a"));
+        for expected in [
+            "name=\"ilfilehash\"\r\n\r\na41c7d6e0f",
+            "name=\"cmd[uploadFile]\"\r\n\r\nHochladen",
+            "name=\"deliver[]\"; filename=\"loesung.pdf\"",
+            "%PDF-1.4 loesung",
+            "name=\"deliver[]\"; filename=\"code.zip\"",
+        ] {
+            assert!(upload.body.contains(expected), "missing {expected}");
+        }
+        assert!(!upload.body.contains("cmd[submissionScreen]"));
+    }
+
+    #[tokio::test]
+    async fn confirms_the_submission() {
+        let (client, requests) = serve(false, Answer::Confirm).await;
+        let files = [homework("blatt3.pdf", "%PDF-1.4 blatt")];
+
+        let submitted = client.submit_files(SUBMIT_URI, &files).await.unwrap();
+        assert_eq!(submitted.len(), 2);
+
+        let requests = requests.lock().unwrap();
+        let confirmation = requests
+            .iter()
+            .find(|request| request.body.contains("cmd%5BconfirmSubmission%5D"))
+            .expect("the confirmation was not posted");
+        assert!(confirmation.body.contains("file_id%5B%5D=9031"));
+        assert!(!confirmation.body.contains("cmd%5BsubmissionScreen%5D"));
+    }
+
+    #[tokio::test]
+    async fn refuses_when_submissions_are_closed() {
+        let (client, requests) = serve(true, Answer::Redirect).await;
+        let files = [homework("spaet.pdf", "%PDF-1.4 spaet")];
+
+        let err = client.submit_files(SUBMIT_URI, &files).await.unwrap_err();
+        assert!(err.to_string().contains("Die Abgabefrist ist abgelaufen"));
+        assert!(uploads(&requests.lock().unwrap()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_errors_of_ilias() {
+        let (client, _) = serve(false, Answer::Error).await;
+        let files = [homework("riesig.pdf", "%PDF-1.4 riesig")];
+
+        let err = client.submit_files(SUBMIT_URI, &files).await.unwrap_err();
+        assert!(err.to_string().contains("Die Datei ist zu groß"));
+    }
+
+    #[test]
+    fn finds_upload_form_behind_link() {
+        let page = Url::parse(&format!("https://ilias.example/{SUBMIT_URI}")).unwrap();
+        let html = Html::parse_document(SUBMISSION_SCREEN);
+        assert!(
+            matches!(upload(&html, &page), Upload::Link(link) if link.as_str().contains("cmd=uploadForm"))
+        );
+        assert!(submitted_files(&html).is_empty());
+    }
+}
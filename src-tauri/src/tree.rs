@@ -1,19 +1,35 @@
 use std::{
-    path::PathBuf,
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
+use chrono::Local;
 use futures::future::join_all;
 use lazy_static::lazy_static;
+use log::{info, warn};
 
 use scraper::{ElementRef, Selector};
 use serde::Serialize;
 use thiserror::Error;
-use tokio::task::JoinHandle;
+use tokio::{sync::oneshot, task::JoinHandle};
 
 use crate::{
+    arena::NodeArena,
+    calendar,
     client::{ClientError, IliasClient},
-    ilias::{IlNode, IlNodeType, ILIAS_ROOT, ROOT_PATH},
+    conflicts::{self, Conflict},
+    download_queue::{DownloadQueue, Priority},
+    exercise,
+    file_type::with_extension,
+    forum::{self, ForumStore, Thread},
+    ilias::{IlNode, IlNodeType, ROOT_PATH},
+    iliasignore::IgnoreRules,
+    integrity::{self, Problem},
+    policy::{self, SyncPolicy},
+    progress::Progress,
+    settings::Settings,
+    versions,
 };
 
 lazy_static! {
@@ -22,14 +38,56 @@ lazy_static! {
     pub static ref LINK: Selector = Selector::parse(".il_ContainerItemTitle > a").unwrap();
     pub static ref PROPERTY: Selector = Selector::parse(".il_ItemProperty").unwrap();
     pub static ref IMAGE: Selector = Selector::parse(".ilListItemIcon").unwrap();
+    pub static ref DESCRIPTION: Selector = Selector::parse(".il_Description").unwrap();
 
     // selectors for root-node
     pub static ref ROOT_CONTAINERS: Selector = Selector::parse(".il-item").unwrap();
     pub static ref ROOT_IMAGE: Selector = Selector::parse(".icon").unwrap();
     pub static ref ROOT_LINK: Selector = Selector::parse(".il-item-title > a").unwrap();
+    pub static ref ROOT_DESCRIPTION: Selector = Selector::parse(".il-item-description").unwrap();
 
 }
 
+/// The name a node with `title` gets on disk
+pub fn path_segment(title: &str) -> String {
+    let mut chars = title.chars();
+    let start = chars.next();
+    let rest = chars.filter_map(|character| match character {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => None,
+        ' ' => Some('_'),
+        c => Some(c),
+    });
+    start.into_iter().chain(rest).collect()
+}
+
+/// Parses sizes like `1,5 MB` or `830 KB`
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.replace("&nbsp;", " ");
+    let (number, unit) = text.trim().split_once(char::is_whitespace)?;
+    let factor = match unit.trim() {
+        "B" | "Bytes" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return None,
+    };
+    // german listings use a decimal comma
+    let number = if number.contains(',') {
+        number.replace('.', "").replace(',', ".")
+    } else {
+        number.to_string()
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * factor as f64) as u64)
+}
+
+/// The text of a description element, `None` if it is empty
+fn description(element: ElementRef) -> Option<String> {
+    let text = element.text().collect::<Vec<_>>().join(" ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
 #[derive(Debug)]
 struct HypNode<'a> {
     element: ElementRef<'a>,
@@ -52,6 +110,48 @@ impl<'a> HypNode<'a> {
         let end_index = start_index + img_src[start_index..].find(".svg")?;
         Some(&img_src[start_index..end_index])
     }
+    fn description(&self) -> Option<String> {
+        description(self.element.select(&DESCRIPTION).next()?)
+    }
+    /// Size of a file as listed in the item properties
+    fn size(&self) -> Option<u64> {
+        self.element
+            .select(&PROPERTY)
+            .find_map(|property| parse_size(&property.inner_html()))
+    }
+    /// Extension of a file as listed in the first item property
+    fn extension(&self) -> Option<String> {
+        let property = self.element.select(&PROPERTY).next()?;
+        let text = property.text().collect::<String>();
+        let text = text.trim();
+        (!text.is_empty() && text.len() <= 5 && text.chars().all(|c| c.is_ascii_alphanumeric()))
+            .then(|| text.to_lowercase())
+    }
+    /// `path` of a file with its listed extension, so that it is known before the download
+    fn with_listed_extension(&self, path: PathBuf) -> PathBuf {
+        match self.extension() {
+            Some(extension) => with_extension(&path, &extension),
+            None => path,
+        }
+    }
+    /// Dates and place of a session, the dates are part of its title
+    fn session(&self, title: &str) -> IlNodeType {
+        let (start, end) = match calendar::parse_session(title, Local::now().date_naive()) {
+            Some((start, end)) => (Some(start), end),
+            None => (None, None),
+        };
+        let location = self.element.select(&PROPERTY).find_map(|property| {
+            let text = property.text().collect::<String>();
+            let (label, value) = text.split_once(':')?;
+            let label = label.trim().to_lowercase();
+            (label == "ort" || label == "location").then(|| value.trim().to_string())
+        });
+        IlNodeType::Session {
+            start,
+            end,
+            location,
+        }
+    }
     fn version(&self) -> Option<usize> {
         let inner_html = self.element.select(&PROPERTY).nth(2)?.inner_html();
         let start_index = inner_html.find("Version: ")? + "Version: ".len();
@@ -59,63 +159,55 @@ impl<'a> HypNode<'a> {
 
         inner_html[start_index..end_index].parse().ok()
     }
+    /// Checks whether ilias still serves the version of `node` we know about.
+    /// If not, `node` is updated to the new version.
     pub fn same_version(self, node: &mut IlNode) -> bool {
-        if node.uri == self.uri().expect("can't extract uri from node") {
-            true
-        } else {
-            match &mut node.breed {
-                IlNodeType::File { version, .. } => {
-                    if let Some(new_version) = self.version() {
-                        if *version == new_version {
-                            true
-                        } else {
-                            *version = new_version;
-                            false
-                        }
-                    } else {
-                        true
-                    }
+        match &mut node.breed {
+            IlNodeType::File { version, .. } => match self.version() {
+                Some(new_version) if *version != new_version => {
+                    *version = new_version;
+                    false
                 }
                 _ => true,
-            }
+            },
+            _ => true,
         }
     }
     pub fn into_node(self, mut path: PathBuf) -> Option<IlNode> {
         let title = self.title()?;
-
-        let mut chars = title.chars();
-        let start = chars.next().unwrap();
-        let rest = chars
-            .filter_map(|character| match character {
-                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => None,
-                ' ' => Some('_'),
-                c => Some(c),
-            });
-        path.push(std::iter::once(start).chain(rest).collect::<String>());
+        path.push(path_segment(&title));
 
         let breed = match self.icon_name() {
-            Some("fold") => Some(IlNodeType::Folder {
-                store_files: false,
-                path,
-            }),
-            Some("crs") => Some(IlNodeType::Folder {
-                store_files: false,
-                path,
-            }),
+            Some("fold" | "crs") => Some(IlNodeType::Folder { path, policy: None }),
             Some("frm") => Some(IlNodeType::Forum),
             Some("webr") => Some(IlNodeType::DirectLink),
             Some("file") => Some(IlNodeType::File {
                 local: true,
-                path,
+                path: self.with_listed_extension(path.clone()),
                 version: self.version().unwrap_or(0),
+                original_name: None,
+                meta: None,
+                conflict: None,
+                size: self.size(),
+                over_limit: false,
             }),
             Some("file_inline") => Some(IlNodeType::File {
                 local: true,
-                path,
+                path: self.with_listed_extension(path.clone()),
                 version: self.version().unwrap_or(0),
+                original_name: None,
+                meta: None,
+                conflict: None,
+                size: self.size(),
+                over_limit: false,
+            }),
+            Some("xvid") => Some(IlNodeType::Video {
+                path,
+                meta: None,
+                info: None,
             }),
-            Some("xvid") => Some(IlNodeType::Video),
             Some("exc") => Some(IlNodeType::Exercise),
+            Some("sess") => Some(self.session(&title)),
             _ => None,
         };
         Some(IlNode {
@@ -124,6 +216,10 @@ impl<'a> HypNode<'a> {
             title,
             uri: self.uri()?.to_string(),
             visible: true,
+            ignored: false,
+            description: self.description(),
+            id: 0,
+            parent: None,
         })
     }
 
@@ -132,120 +228,675 @@ impl<'a> HypNode<'a> {
     }
 }
 
+/// The nodes of the last crawl by uri, to match them with a fresh listing.
+/// A listing may show the same uri twice, those nodes are matched in order.
+struct Known(HashMap<String, VecDeque<Arc<Mutex<IlNode>>>>);
+
+impl Known {
+    fn new(nodes: Vec<Arc<Mutex<IlNode>>>) -> Self {
+        let mut known = HashMap::<_, VecDeque<_>>::new();
+        for node in nodes {
+            let uri = node.lock().unwrap().uri.clone();
+            known.entry(uri).or_default().push_back(node);
+        }
+        Self(known)
+    }
+
+    fn take(&mut self, uri: &str) -> Option<Arc<Mutex<IlNode>>> {
+        self.0.get_mut(uri)?.pop_front()
+    }
+}
+
+/// Everything a crawl shares between its tasks
+#[derive(Clone)]
+pub struct SyncContext {
+    pub client: Arc<IliasClient>,
+    pub ignore: Arc<IgnoreRules>,
+    pub forums: Arc<Mutex<ForumStore>>,
+    pub settings: Arc<Settings>,
+    pub queue: Arc<DownloadQueue>,
+    pub progress: Arc<Progress>,
+    /// Indexes the nodes as the crawl replaces children
+    pub arena: Arc<Mutex<NodeArena>>,
+}
+
+impl SyncContext {
+    /// Queues the download of `node` instead of starting it right away
+    fn download(
+        &self,
+        node: Arc<Mutex<IlNode>>,
+        priority: Priority,
+    ) -> oneshot::Receiver<anyhow::Result<()>> {
+        let ctx = self.clone();
+        self.queue.submit(priority, async move {
+            let uri = node.lock().unwrap().uri.clone();
+            let result = ctx
+                .client
+                .download_file(
+                    node,
+                    Some(&ctx.settings.downloads),
+                    ctx.queue.throttle(),
+                    &ctx.progress,
+                )
+                .await;
+            ctx.progress.file_finished(&uri, &result);
+            result
+        })
+    }
+
+    /// Queues resolving the video page of `node` and, with `download`, the download of the recording
+    fn video(
+        &self,
+        node: Arc<Mutex<IlNode>>,
+        download: bool,
+    ) -> oneshot::Receiver<anyhow::Result<()>> {
+        let ctx = self.clone();
+        self.queue.submit(Priority::New, async move {
+            let uri = node.lock().unwrap().uri.clone();
+            if !download {
+                let (info, _) = ctx.client.video_sources(&uri).await?;
+                if let IlNodeType::Video { info: known, .. } = &mut node.lock().unwrap().breed {
+                    *known = Some(info);
+                }
+                return Ok(());
+            }
+            let result = ctx
+                .client
+                .download_video(
+                    node,
+                    ctx.settings.videos.quality,
+                    Some(&ctx.settings.downloads),
+                    ctx.queue.throttle(),
+                    &ctx.progress,
+                )
+                .await;
+            ctx.progress.file_finished(&uri, &result);
+            result
+        })
+    }
+
+    /// Queues the download of a new version of `node`.
+    /// If the local copy was edited, the new version is put next to it and the conflict recorded.
+    /// Otherwise the local copy of `old_version` is kept as an earlier version.
+    fn update(
+        &self,
+        node: Arc<Mutex<IlNode>>,
+        old_version: usize,
+    ) -> oneshot::Receiver<anyhow::Result<()>> {
+        let ctx = self.clone();
+        self.queue.submit(Priority::Update, async move {
+            let (uri, path, meta) = {
+                let mut node = node.lock().unwrap();
+                let meta = node.breed.meta_mut().unwrap().clone();
+                (
+                    node.uri.clone(),
+                    node.breed.file_path().cloned().unwrap(),
+                    meta,
+                )
+            };
+            let edited = meta.is_some_and(|meta| {
+                matches!(
+                    integrity::check(&path, &meta),
+                    Some(Problem::Modified | Problem::Truncated { .. })
+                )
+            });
+
+            let result = if edited {
+                ctx.download_beside(&node, &uri, &path, old_version).await
+            } else {
+                ctx.replace_version(&node, &path, old_version).await
+            };
+            if result.is_err() {
+                // try again on the next sync
+                *node.lock().unwrap().breed.version_mut().unwrap() = old_version;
+            }
+            ctx.progress.file_finished(&uri, &result);
+            result
+        })
+    }
+
+    async fn replace_version(
+        &self,
+        node: &Arc<Mutex<IlNode>>,
+        path: &Path,
+        old_version: usize,
+    ) -> anyhow::Result<()> {
+        let settings = &self.settings.versions;
+        // like in [Self::fits_limits], downloaded files keep being updated
+        let downloaded = node.lock().unwrap().breed.meta_mut().unwrap().is_some();
+        let limits = (!downloaded).then_some(&self.settings.downloads);
+        let archived = versions::archive(path, old_version, settings)?;
+        let result = self
+            .client
+            .download_file(node.clone(), limits, self.queue.throttle(), &self.progress)
+            .await;
+        // the new version might only be available remotely
+        let stored = node
+            .lock()
+            .unwrap()
+            .breed
+            .file_path()
+            .is_some_and(|path| path.exists());
+        match (&result, archived) {
+            (Ok(_), Some(_)) if stored => {
+                if let Err(err) = versions::prune(path, settings) {
+                    warn!("can't prune old versions of {path:?}: {err}");
+                }
+            }
+            (_, Some(archived)) => {
+                std::fs::rename(archived, path).ok();
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Whether the sync may download the file or video `node`, marking files as remote-only if not
+    fn fits_limits(&self, node: &Arc<Mutex<IlNode>>) -> bool {
+        let mut node = node.lock().unwrap();
+        if let IlNodeType::Video { meta, .. } = &node.breed {
+            // recordings are stored as mp4, their size is only known once requested
+            return meta.is_some() || self.settings.downloads.allows(Some("mp4"), None);
+        }
+        let IlNodeType::File {
+            path,
+            local,
+            size,
+            over_limit,
+            meta,
+            ..
+        } = &mut node.breed
+        else {
+            return true;
+        };
+        // files that were downloaded anyway, e.g. on demand, keep being updated
+        if *local && meta.is_some() {
+            return true;
+        }
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if !self.settings.downloads.allows(extension, *size) {
+            node.breed.exceed_limits();
+            return false;
+        }
+        if *over_limit {
+            *local = true;
+            *over_limit = false;
+        }
+        *local
+    }
+
+    async fn download_beside(
+        &self,
+        node: &Arc<Mutex<IlNode>>,
+        uri: &str,
+        path: &Path,
+        old_version: usize,
+    ) -> anyhow::Result<()> {
+        let remote_version = *node.lock().unwrap().breed.version_mut().unwrap();
+        info!("{path:?} was edited locally, keeping version {remote_version} next to it");
+        let (remote_path, remote_meta) = self
+            .client
+            .download_copy(
+                uri,
+                &conflicts::remote_path(path, remote_version),
+                self.queue.throttle(),
+                &self.progress,
+            )
+            .await?;
+        let superseded = match &mut node.lock().unwrap().breed {
+            IlNodeType::File { conflict, .. } => {
+                // the local edits stay based on the version of an open conflict
+                let local_version = conflict
+                    .as_ref()
+                    .map_or(old_version, |open| open.local_version);
+                conflict
+                    .replace(Conflict {
+                        local_version,
+                        remote_version,
+                        remote_path: remote_path.clone(),
+                        remote_meta,
+                    })
+                    .filter(|open| open.remote_path != remote_path)
+            }
+            _ => None,
+        };
+        if let Some(open) = superseded {
+            conflicts::discard_remote(&open);
+        }
+        Ok(())
+    }
+}
+
+/// Whether `node` is a file that should be stored locally but never was
+fn missing_download(node: &IlNode) -> bool {
+    match &node.breed {
+        IlNodeType::File {
+            path,
+            local: true,
+            meta: None,
+            ..
+        } => !path.exists(),
+        _ => false,
+    }
+}
+
+/// Crawls `node`, which inherits `policy` from its parent
 pub fn update_node(
-    client: Arc<IliasClient>,
+    ctx: SyncContext,
     node: Arc<Mutex<IlNode>>,
+    policy: SyncPolicy,
 ) -> JoinHandle<Result<Arc<Mutex<IlNode>>, TreeError>> {
+    ctx.progress.container_queued();
+    tokio::spawn(async move {
+        let progress = ctx.progress.clone();
+        let result = crawl_node(ctx, node, policy).await;
+        progress.container_crawled();
+        result
+    })
+}
+
+/// Crawls the threads of the forum `node` into the forum store
+fn update_forum(ctx: SyncContext, node: Arc<Mutex<IlNode>>) -> JoinHandle<Result<(), TreeError>> {
+    ctx.progress.container_queued();
     tokio::spawn(async move {
-        let mut child_handles = vec![];
-        let mut download_handles = vec![];
+        let uri = node.lock().unwrap().uri.clone();
+        let result = crawl_forum(&ctx, &uri).await;
+        ctx.progress.container_crawled();
+        result
+    })
+}
+
+/// Crawls the threads of the forum at `uri`. Only threads whose last post changed since the
+/// last sync are fetched again.
+async fn crawl_forum(ctx: &SyncContext, uri: &str) -> Result<(), TreeError> {
+    let listed = forum::parse_threads(&ctx.client.get_page(uri).await?);
+    let known = ctx.forums.lock().unwrap().threads(uri).to_vec();
+    let known = |listed: &forum::ListedThread| known.iter().find(|thread| thread.uri == listed.uri);
 
-        let (uri, children, path) = {
-            let mut node = node.lock().unwrap();
-            (node.uri.clone(), node.children.take(), node.breed.path())
+    let fetches = listed
+        .iter()
+        .filter(|listed| {
+            known(listed).is_none_or(|thread| {
+                listed.last_post.is_none() || thread.last_post != listed.last_post
+            })
+        })
+        .map(|listed| async move {
+            let posts = ctx
+                .client
+                .get_page(&listed.uri)
+                .await
+                .map(|html| forum::parse_posts(&html));
+            (listed.uri.as_str(), posts)
+        });
+    let mut fetched = join_all(fetches)
+        .await
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut threads = vec![];
+    for listed in &listed {
+        let (posts, last_post) = match fetched.remove(listed.uri.as_str()) {
+            Some(Ok(posts)) => (posts, listed.last_post.clone()),
+            Some(Err(err)) => {
+                warn!("can't crawl thread {}: {err}", listed.title);
+                // keep the posts we know about instead of reporting them again later
+                match known(listed) {
+                    Some(thread) => (thread.posts.clone(), thread.last_post.clone()),
+                    None => continue,
+                }
+            }
+            None => {
+                let thread = known(listed).expect("only known threads aren't fetched");
+                (thread.posts.clone(), thread.last_post.clone())
+            }
         };
+        threads.push(Thread {
+            uri: listed.uri.clone(),
+            title: listed.title.clone(),
+            posts,
+            last_post,
+        });
+    }
+    ctx.forums.lock().unwrap().merge(uri, threads);
+    Ok(())
+}
 
-        let new_children: Vec<Arc<Mutex<IlNode>>> = if let Some(mut children) = children {
-            let html = client.get_page(&uri).await?;
-            let elements = html.select(&CONTAINERS);
-            // build new children from fresh children list
-            elements
+/// Crawls the exercise `node` in `folder` into its assignments and their instruction files
+fn update_exercise(
+    ctx: SyncContext,
+    node: Arc<Mutex<IlNode>>,
+    policy: SyncPolicy,
+    folder: PathBuf,
+) -> JoinHandle<Result<Arc<Mutex<IlNode>>, TreeError>> {
+    ctx.progress.container_queued();
+    tokio::spawn(async move {
+        let progress = ctx.progress.clone();
+        let result = crawl_exercise(ctx, node, policy, folder).await;
+        progress.container_crawled();
+        result
+    })
+}
+
+async fn crawl_exercise(
+    ctx: SyncContext,
+    node: Arc<Mutex<IlNode>>,
+    policy: SyncPolicy,
+    folder: PathBuf,
+) -> Result<Arc<Mutex<IlNode>>, TreeError> {
+    let (uri, title) = {
+        let node = node.lock().unwrap();
+        (node.uri.clone(), node.title.clone())
+    };
+    let assignments = exercise::parse_assignments(&ctx.client.get_page(&uri).await?);
+    let folder = folder.join(path_segment(&title));
+    let mut known = Known::new(node.lock().unwrap().children.clone().unwrap_or_default());
+    let mut download_handles = vec![];
+    let assignments = assignments
+        .into_iter()
+        .map(|assignment| {
+            let assignment_uri = exercise::assignment_uri(&uri, &assignment.id);
+            let assignment_node = known.take(&assignment_uri).unwrap_or_else(|| {
+                Arc::new(Mutex::new(IlNode {
+                    uri: assignment_uri,
+                    title: assignment.title.clone(),
+                    breed: IlNodeType::Assignment {
+                        path: folder.join(path_segment(&assignment.title)),
+                        info: Default::default(),
+                    },
+                    visible: true,
+                    children: Some(vec![]),
+                    ignored: false,
+                    description: None,
+                    id: 0,
+                    parent: None,
+                }))
+            });
+            let (path, mut old_files, ignored) = {
+                let mut node = assignment_node.lock().unwrap();
+                node.title = assignment.title;
+                node.ignored = ctx.ignore.is_ignored(&node, &folder);
+                let IlNodeType::Assignment { path, info } = &mut node.breed else {
+                    unreachable!("assignments are only created above")
+                };
+                *info = assignment.info;
+                let path = path.clone();
+                (
+                    path,
+                    Known::new(node.children.clone().unwrap_or_default()),
+                    node.ignored,
+                )
+            };
+
+            let files = assignment
+                .files
                 .into_iter()
-                .map(HypNode::new)
-                .filter(|hypnode| hypnode.uri().is_some())
-                .filter_map(|hypnode| {
-                    // try to find child in old children
-                    let position = children
-                        .iter()
-                        .position(|child| child.lock().unwrap().uri == hypnode.uri().unwrap());
-
-                    // if we find the child we might replace it
-                    if let Some(node_index) = position {
-                        let node = children.remove(node_index);
-                        let same_node = hypnode.same_version(&mut node.lock().unwrap());
-                        if !same_node {
-                            let node = node.clone();
-                            let client = client.clone();
-                            download_handles.push(tokio::spawn(async move {
-                                client.download_file(node).await
-                            }));
+                .map(|(name, file_uri)| {
+                    let file = old_files.take(&file_uri).unwrap_or_else(|| {
+                        Arc::new(Mutex::new(IlNode {
+                            uri: file_uri,
+                            breed: IlNodeType::File {
+                                path: path.join(path_segment(&name)),
+                                version: 0,
+                                local: policy.downloads(),
+                                original_name: None,
+                                meta: None,
+                                conflict: None,
+                                size: None,
+                                over_limit: false,
+                            },
+                            title: name.clone(),
+                            visible: true,
+                            children: Some(vec![]),
+                            ignored: false,
+                            description: None,
+                            id: 0,
+                            parent: None,
+                        }))
+                    });
+                    let file_ignored = {
+                        let mut file = file.lock().unwrap();
+                        file.title = name;
+                        file.ignored = ctx.ignore.is_ignored(&file, &path);
+                        file.ignored
+                    };
+                    let ignored = ignored || file_ignored;
+                    if policy.downloads()
+                        && !ignored
+                        && ctx.fits_limits(&file)
+                        && missing_download(&file.lock().unwrap())
+                    {
+                        download_handles.push(ctx.download(file.clone(), Priority::New));
+                    }
+                    file
+                })
+                .collect();
+            ctx.arena
+                .lock()
+                .unwrap()
+                .set_children(&assignment_node, Some(files));
+            assignment_node
+        })
+        .collect::<Vec<_>>();
+
+    for result in join_all(download_handles).await {
+        if let Ok(Err(err)) = result {
+            warn!("download failed: {err}");
+        }
+    }
+    ctx.arena
+        .lock()
+        .unwrap()
+        .set_children(&node, Some(assignments));
+    Ok(node)
+}
+
+async fn crawl_node(
+    ctx: SyncContext,
+    node: Arc<Mutex<IlNode>>,
+    inherited: SyncPolicy,
+) -> Result<Arc<Mutex<IlNode>>, TreeError> {
+    let policy = policy::effective(&node.lock().unwrap().breed, inherited);
+    if policy == SyncPolicy::Never {
+        return Ok(node);
+    }
+    let client = ctx.client.clone();
+    let mut child_handles = vec![];
+    let mut download_handles = vec![];
+    let mut forum_handles = vec![];
+
+    let (uri, children, path) = {
+        let node = node.lock().unwrap();
+        (node.uri.clone(), node.children.clone(), node.breed.path())
+    };
+    let parent = path.clone().unwrap_or_default();
+    let ignore = |node: &Arc<Mutex<IlNode>>| {
+        let mut node = node.lock().unwrap();
+        node.ignored = ctx.ignore.is_ignored(&node, &parent);
+        node.ignored
+    };
+
+    let new_children: Vec<Arc<Mutex<IlNode>>> = if let Some(children) = children {
+        // the old children stay in place until the fresh listing replaces them
+        let html = client.get_page(&uri).await?;
+        let mut known = Known::new(children);
+        let elements = html.select(&CONTAINERS);
+        // build new children from fresh children list
+        elements
+            .into_iter()
+            .map(HypNode::new)
+            .filter(|hypnode| hypnode.uri().is_some())
+            .filter_map(|hypnode| {
+                // if we know the child we might replace it
+                if let Some(node) = known.take(hypnode.uri().unwrap()) {
+                    if let IlNodeType::File {
+                        size, path, meta, ..
+                    } = &mut node.lock().unwrap().breed
+                    {
+                        *size = hypnode.size().or(*size);
+                        // files crawled before the extension was taken from the listing
+                        if meta.is_none() && !path.exists() {
+                            *path = hypnode.with_listed_extension(path.clone());
                         }
+                    }
+                    {
+                        let mut node = node.lock().unwrap();
+                        node.description = hypnode.description();
+                        if let Some(title) = hypnode.title() {
+                            // rescheduled sessions get a new title with the new dates
+                            if let IlNodeType::Session { .. } = node.breed {
+                                node.breed = hypnode.session(&title);
+                            }
+                            node.title = title;
+                        }
+                    }
+                    let old_version = node.lock().unwrap().breed.version_mut().copied();
+                    let same_node = hypnode.same_version(&mut node.lock().unwrap());
+                    let ignored = ignore(&node);
+                    if policy.downloads() && !ignored && ctx.fits_limits(&node) {
+                        if let (false, Some(old_version)) = (same_node, old_version) {
+                            download_handles.push(ctx.update(node.clone(), old_version));
+                        } else if missing_download(&node.lock().unwrap()) {
+                            download_handles.push(ctx.download(node.clone(), Priority::New));
+                        }
+                    }
+                    Some(node)
+                } else {
+                    if let Some(node) = hypnode.into_node(
+                        path.as_ref()
+                            .expect("program logic shoul ensure this")
+                            .clone(),
+                    ) {
+                        let node = Arc::new(Mutex::new(node));
+                        if let Some(local) = node.lock().unwrap().breed.get_local() {
+                            *local = policy.downloads();
+                        }
+                        let ignored = ignore(&node);
+                        if policy.downloads()
+                            && !ignored
+                            && node.lock().unwrap().breed.is_file()
+                            && ctx.fits_limits(&node)
+                        {
+                            download_handles.push(ctx.download(node.clone(), Priority::New));
+                        };
                         Some(node)
                     } else {
-                        if let Some(node) = hypnode.into_node(
-                            path.as_ref()
-                                .expect("program logic shoul ensure this")
-                                .clone(),
-                        ) {
-                            let node = Arc::new(Mutex::new(node));
-                            let node_clone = node.clone();
-                            if node.lock().unwrap().breed.is_file() {
-                                let client = client.clone();
-                                download_handles.push(tokio::spawn(async move {
-                                    client.download_file(node_clone).await
-                                }));
-                            };
-                            Some(node)
-                        } else {
-                            None
-                        }
+                        None
                     }
-                })
-                .collect()
-        } else {
-            vec![]
-        };
-
-        for child in &new_children {
-            match child.lock().unwrap().breed.clone() {
-                IlNodeType::Folder { .. } => {
-                    child_handles.push(update_node(client.clone(), child.clone()));
                 }
-                IlNodeType::DirectLink => {
-                    let child_clone = child.clone();
-                    let client_clone = client.clone();
-                    tokio::spawn(async move {
-                        client_clone.flatten_link(&child_clone).await.unwrap();
-                    });
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    for child in &new_children {
+        let (breed, ignored) = {
+            let child = child.lock().unwrap();
+            (child.breed.clone(), child.ignored)
+        };
+        match breed {
+            IlNodeType::Folder { .. } if !ignored => {
+                child_handles.push(update_node(ctx.clone(), child.clone(), policy));
+            }
+            IlNodeType::Video { meta, info, .. } if !ignored => {
+                let download = policy.downloads()
+                    && ctx.settings.videos.download
+                    && meta.is_none()
+                    && ctx.fits_limits(child);
+                if download || info.is_none() {
+                    download_handles.push(ctx.video(child.clone(), download));
                 }
-                _ => {}
             }
+            IlNodeType::Exercise if !ignored => {
+                child_handles.push(update_exercise(
+                    ctx.clone(),
+                    child.clone(),
+                    policy,
+                    parent.clone(),
+                ));
+            }
+            IlNodeType::Forum if !ignored => {
+                forum_handles.push(update_forum(ctx.clone(), child.clone()));
+            }
+            IlNodeType::DirectLink => {
+                let child = child.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    let from = child.lock().unwrap().uri.clone();
+                    ctx.client.flatten_link(&child).await.unwrap();
+                    ctx.arena.lock().unwrap().moved(&child, &from);
+                });
+            }
+            _ => {}
+        }
+    }
+    join_all(child_handles).await;
+    for result in join_all(forum_handles).await {
+        if let Ok(Err(err)) = result {
+            warn!("crawling forum failed: {err}");
         }
-        join_all(child_handles).await;
-        join_all(download_handles).await;
-        if new_children.len() > 0 {
-            node.lock().unwrap().children = Some(new_children);
-        } else {
-            node.lock().unwrap().children = None;
+    }
+    for result in join_all(download_handles).await {
+        if let Ok(Err(err)) = result {
+            warn!("download failed: {err}");
         }
-        Ok(node)
-    })
+    }
+    let new_children = (!new_children.is_empty()).then_some(new_children);
+    ctx.arena.lock().unwrap().set_children(&node, new_children);
+    Ok(node)
+}
+
+/// Which courses a sync crawls, by their title or uri
+#[derive(Debug, Clone, Default)]
+pub enum CourseFilter {
+    #[default]
+    All,
+    Only(HashSet<String>),
+    Except(HashSet<String>),
 }
 
+impl CourseFilter {
+    pub fn matches(&self, course: &IlNode) -> bool {
+        let named =
+            |names: &HashSet<String>| names.contains(&course.title) || names.contains(&course.uri);
+        match self {
+            CourseFilter::All => true,
+            CourseFilter::Only(names) => named(names),
+            CourseFilter::Except(names) => !named(names),
+        }
+    }
+}
+
+/// Crawls the courses matching `filter`, the others are kept as they are
 pub fn update_root(
-    client: Arc<IliasClient>,
+    ctx: SyncContext,
     root: Arc<Mutex<IlNode>>,
+    filter: CourseFilter,
 ) -> JoinHandle<Result<(), TreeError>> {
-    let mut root_children = root.lock().unwrap().children.take();
+    let root_children = root.lock().unwrap().children.clone();
     tokio::spawn(async move {
+        let client = &ctx.client;
         let children = {
-            let html = client.get_page(ILIAS_ROOT).await?;
+            // the known courses stay in place until the fresh listing replaces them
+            let html = client.get_page(&client.instance().root_uri).await?;
+            let mut known = Known::new(root_children.unwrap_or_default());
             let elements = html.select(&ROOT_CONTAINERS);
             elements
                 .filter(is_kurs)
                 .map(|elem| {
                     let link = elem.select(&ROOT_LINK).next().unwrap();
                     let uri = link.value().attr("href").unwrap().to_string();
-                    if let Some(children) = &mut root_children {
-                        if let Some(position) = children
-                            .iter()
-                            .position(|node| node.lock().unwrap().uri == uri)
+                    let title = link.inner_html();
+                    let description = elem.select(&ROOT_DESCRIPTION).next().and_then(description);
+                    if let Some(node) = known.take(&uri) {
                         {
-                            return children.remove(position);
+                            let mut course = node.lock().unwrap();
+                            course.title = title;
+                            course.description = description;
                         }
+                        return node;
                     }
 
-                    let title = link.inner_html();
                     let folder = title
                         .chars()
                         .filter_map(|character| match character {
@@ -257,7 +908,6 @@ pub fn update_root(
                     Arc::new(Mutex::new(IlNode {
                         uri,
                         breed: IlNodeType::Folder {
-                            store_files: true,
                             // This is done sooo fishy xD
                             path: Some(PathBuf::from(ROOT_PATH))
                                 .map(|mut path| {
@@ -265,10 +915,15 @@ pub fn update_root(
                                     path
                                 })
                                 .unwrap(),
+                            policy: None,
                         },
                         title,
                         visible: true,
                         children: Some(vec![]),
+                        ignored: false,
+                        description,
+                        id: 0,
+                        parent: None,
                     }))
                 })
                 .collect::<Vec<_>>()
@@ -276,14 +931,16 @@ pub fn update_root(
 
         let handles = children
             .iter()
-            .map(|child| update_node(client.clone(), child.clone()));
+            .filter(|child| {
+                let mut child = child.lock().unwrap();
+                child.ignored = ctx.ignore.is_ignored(&child, Path::new(ROOT_PATH));
+                filter.matches(&child) && !child.ignored
+            })
+            .map(|child| update_node(ctx.clone(), child.clone(), SyncPolicy::default()));
         join_all(handles).await;
 
-        if children.len() > 0 {
-            root.lock().unwrap().children = Some(children);
-        } else {
-            root.lock().unwrap().children = None;
-        };
+        let children = (!children.is_empty()).then_some(children);
+        ctx.arena.lock().unwrap().set_children(&root, children);
         Ok(())
     })
 }
@@ -301,6 +958,196 @@ fn is_kurs(element: &ElementRef) -> bool {
 
 #[derive(Debug, Error, Serialize)]
 pub enum TreeError {
+    #[error("a sync is already running")]
+    AlreadyRunning,
     #[error(transparent)]
     Client(#[from] ClientError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arena::NodeId,
+        ilias::ILIAS_ROOT,
+        stub::{self, Reply},
+    };
+    use scraper::Html;
+
+    fn listing(icon: &str, title: &str, properties: &[&str]) -> Html {
+        let properties = properties
+            .iter()
+            .map(|property| format!(r#"<span class="il_ItemProperty">{property}</span>"#))
+            .collect::<String>();
+        Html::parse_document(&format!(
+            r#"<div class="ilContainerListItemOuter">
+                <img class="ilListItemIcon" src="templates/icon_{icon}.svg">
+                <div class="il_ContainerItemTitle"><a href="goto.php?target=file_1">{title}</a></div>
+                <div class="il_ItemProperties">{properties}</div>
+            </div>"#
+        ))
+    }
+
+    fn crawl(html: &Html) -> IlNode {
+        let element = html.select(&CONTAINERS).next().unwrap();
+        HypNode::new(element)
+            .into_node(PathBuf::from("studium/Kurs"))
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_listed_sizes() {
+        assert_eq!(parse_size("830 KB"), Some(830 * 1024));
+        assert_eq!(parse_size("1,5&nbsp;MB"), Some(1536 * 1024));
+        assert_eq!(parse_size("1.234,5 KB"), Some(1_264_128));
+        assert_eq!(parse_size("2.5 GB"), Some(5 << 29));
+        assert_eq!(parse_size("12 Bytes"), Some(12));
+        assert_eq!(parse_size("Version: 2"), None);
+        assert_eq!(parse_size("pdf"), None);
+    }
+
+    #[test]
+    fn files_get_their_listed_extension() {
+        let html = listing(
+            "file",
+            "Kapitel 2.1",
+            &["PDF", "1,5 MB", "Version: 3&nbsp;&nbsp;"],
+        );
+        let node = crawl(&html);
+        assert_eq!(
+            node.breed.file_path(),
+            Some(&PathBuf::from("studium/Kurs/Kapitel_2.1.pdf"))
+        );
+        assert_eq!(node.breed.size(), Some(1536 * 1024));
+    }
+
+    #[test]
+    fn files_without_a_listed_extension_keep_their_title() {
+        let html = listing("file", "Skript", &["Verfügbarkeit: bis 1. Mai"]);
+        assert_eq!(
+            crawl(&html).breed.file_path(),
+            Some(&PathBuf::from("studium/Kurs/Skript"))
+        );
+    }
+
+    #[tokio::test]
+    async fn refetches_only_threads_with_new_posts() {
+        let last_post = Arc::new(Mutex::new("7".to_string()));
+        let listing = last_post.clone();
+        let (client, requests) = stub::serve(move |line| {
+            if line.contains("cmd=showThreads") {
+                Reply::Page(format!(
+                    r#"<a href="ilias.php?thr_pk=1&cmd=viewThread">Eins</a>
+                    <a href="ilias.php?thr_pk=1&pos_pk=3">1. Okt</a>
+                    <a href="ilias.php?thr_pk=2&cmd=viewThread">Zwei</a>
+                    <a href="ilias.php?thr_pk=2&pos_pk={}">2. Okt</a>"#,
+                    listing.lock().unwrap()
+                ))
+            } else {
+                Reply::Page(r#"<div class="ilFrmPostRow"><a id="fpost_3"></a></div>"#.to_string())
+            }
+        })
+        .await;
+        let ctx = stub::context(client);
+        let forum = "ilias.php?ref_id=5&cmd=showThreads";
+        let crawl = || async {
+            requests.lock().unwrap().clear();
+            crawl_forum(&ctx, forum).await.unwrap();
+            requests.lock().unwrap().len()
+        };
+
+        assert_eq!(crawl().await, 3);
+        assert_eq!(crawl().await, 1);
+        *last_post.lock().unwrap() = "8".to_string();
+        assert_eq!(crawl().await, 2);
+        assert_eq!(
+            requests.lock().unwrap()[1],
+            "GET /ilias.php?thr_pk=2&cmd=viewThread HTTP/1.1"
+        );
+        assert_eq!(ctx.forums.lock().unwrap().threads(forum).len(), 2);
+    }
+
+    /// Uri and id of every node in tree order
+    fn ids(root: &Arc<Mutex<IlNode>>) -> Vec<(String, NodeId)> {
+        let mut ids = vec![];
+        crate::ilias::walk(root, &mut |_, node| ids.push((node.uri.clone(), node.id)));
+        ids
+    }
+
+    #[tokio::test]
+    async fn nodes_keep_their_ids_across_syncs() {
+        let listed = Arc::new(Mutex::new(vec!["fold_a", "fold_b", "fold_b", "fold_c"]));
+        let course = listed.clone();
+        let (client, _) = stub::serve(move |line| {
+            let folders = if line.contains("ilmembershipoverviewgui") {
+                return Reply::Page(stub::courses(&[("goto.php?target=crs_1", "Analysis")]));
+            } else if line.contains("crs_1") {
+                course.lock().unwrap().clone()
+            } else if line.contains("fold_a") {
+                vec!["fold_x"]
+            } else {
+                vec![]
+            };
+            let uris = folders
+                .iter()
+                .map(|target| format!("goto.php?target={target}"))
+                .collect::<Vec<_>>();
+            let items = folders
+                .iter()
+                .zip(&uris)
+                .map(|(target, uri)| ("fold", uri.as_str(), *target))
+                .collect::<Vec<_>>();
+            Reply::Page(stub::items(&items))
+        })
+        .await;
+        let root = Arc::new(Mutex::new(IlNode::default()));
+        let mut ctx = stub::context(client);
+        ctx.arena = Arc::new(Mutex::new(NodeArena::new(&root)));
+        let sync = || async {
+            update_root(ctx.clone(), root.clone(), CourseFilter::All)
+                .await
+                .unwrap()
+                .unwrap();
+            ids(&root)
+        };
+        let uri = |target: &str| format!("goto.php?target={target}");
+
+        let first = sync().await;
+        assert_eq!(first.len(), 7);
+        let unique = first.iter().map(|(_, id)| *id).collect::<HashSet<_>>();
+        assert_eq!(unique.len(), first.len());
+        assert!(!unique.contains(&0));
+        assert_eq!(sync().await, first);
+
+        *listed.lock().unwrap() = vec!["fold_a", "fold_b", "fold_b", "fold_d"];
+        let third = sync().await;
+        let c = first
+            .iter()
+            .find(|(node, _)| *node == uri("fold_c"))
+            .unwrap();
+        let d = third
+            .iter()
+            .find(|(node, _)| *node == uri("fold_d"))
+            .unwrap();
+        assert_eq!(
+            third.iter().filter(|node| *node != d).collect::<Vec<_>>(),
+            first.iter().filter(|node| *node != c).collect::<Vec<_>>()
+        );
+        assert!(d.1 > unique.into_iter().max().unwrap());
+
+        let arena = ctx.arena.lock().unwrap();
+        assert!(arena.get(c.1).is_none());
+        assert_eq!(arena.id(&uri("fold_c")), None);
+        assert_eq!(arena.id(&uri("fold_d")), Some(d.1));
+        let x = arena.id(&uri("fold_x")).unwrap();
+        let ancestors = arena
+            .ancestors(x)
+            .iter()
+            .map(|node| node.lock().unwrap().uri.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ancestors,
+            [ILIAS_ROOT.to_string(), uri("crs_1"), uri("fold_a")]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::settings::{VersionLayout, VersionSettings};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Sibling directory old versions are kept in with [VersionLayout::Directory]
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// An earlier version of a file that is kept locally
+#[derive(Debug, Serialize, Clone)]
+pub struct FileVersion {
+    pub version: usize,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+fn archive_dir(path: &Path, layout: VersionLayout) -> PathBuf {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match layout {
+        VersionLayout::Directory => dir.join(VERSIONS_DIR),
+        VersionLayout::Suffix => dir.to_path_buf(),
+    }
+}
+
+/// `folie.pdf` -> (`folie.v`, `.pdf`), the version number goes in between
+fn name_parts(path: &Path) -> (OsString, OsString) {
+    let mut prefix = OsString::from(path.file_stem().unwrap_or_default());
+    prefix.push(".v");
+    let suffix = match path.extension() {
+        Some(extension) => {
+            let mut suffix = OsString::from(".");
+            suffix.push(extension);
+            suffix
+        }
+        None => OsString::new(),
+    };
+    (prefix, suffix)
+}
+
+fn archived_path(path: &Path, version: usize, layout: VersionLayout) -> PathBuf {
+    let (mut name, suffix) = name_parts(path);
+    name.push(version.to_string());
+    name.push(suffix);
+    archive_dir(path, layout).join(name)
+}
+
+/// Moves the local copy of `path` aside as `version`, so that a newer version can take its place.
+/// Returns where it was moved to or `None` if there was no local copy.
+pub fn archive(
+    path: &Path,
+    version: usize,
+    settings: &VersionSettings,
+) -> io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let archived = archived_path(path, version, settings.layout);
+    fs::create_dir_all(archived.parent().unwrap())?;
+    fs::rename(path, &archived)?;
+    Ok(Some(archived))
+}
+
+/// Earlier versions of `path`, newest first
+pub fn list(path: &Path, settings: &VersionSettings) -> io::Result<Vec<FileVersion>> {
+    let dir = archive_dir(path, settings.layout);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let (prefix, suffix) = name_parts(path);
+    let (prefix, suffix) = (prefix.to_string_lossy(), suffix.to_string_lossy());
+
+    let mut versions = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            let version = name
+                .to_str()?
+                .strip_prefix(&*prefix)?
+                .strip_suffix(&*suffix)?
+                .parse()
+                .ok()?;
+            Some(FileVersion {
+                version,
+                path: entry.path(),
+                size: entry.metadata().ok()?.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    versions.sort_by_key(|version| Reverse(version.version));
+    Ok(versions)
+}
+
+/// Deletes the oldest versions of `path` beyond the retention count
+pub fn prune(path: &Path, settings: &VersionSettings) -> io::Result<()> {
+    for old in list(path, settings)?.into_iter().skip(settings.keep) {
+        fs::remove_file(old.path)?;
+    }
+    Ok(())
+}
+
+/// Puts `version` back in place of `path`. The current copy is archived as `current`,
+/// restoring `current` itself does nothing.
+pub fn restore(
+    path: &Path,
+    version: usize,
+    current: usize,
+    settings: &VersionSettings,
+) -> Result<()> {
+    // the file already is that version, and archiving it would replace the kept copy
+    if version == current {
+        return Ok(());
+    }
+    let restored = list(path, settings)?
+        .into_iter()
+        .find(|candidate| candidate.version == version)
+        .ok_or(anyhow!("version {version} of {path:?} isn't kept locally"))?;
+    archive(path, current, settings)?;
+    fs::rename(restored.path, path)?;
+    Ok(())
+}
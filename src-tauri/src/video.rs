@@ -0,0 +1,331 @@
+use crate::settings::Quality;
+use lazy_static::lazy_static;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+lazy_static! {
+    static ref SOURCES: Selector = Selector::parse("video[src], video source[src]").unwrap();
+    static ref EMBEDS: Selector = Selector::parse("a[href], iframe[src]").unwrap();
+}
+
+/// What is known about a recording
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
+pub struct VideoInfo {
+    /// In seconds
+    pub duration: Option<u64>,
+    /// As given by the server, usually RFC 3339
+    pub recorded_at: Option<String>,
+    pub presenter: Option<String>,
+    /// Heights of the available mp4 sources, highest first
+    pub qualities: Vec<u32>,
+}
+
+/// A downloadable rendition of a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoSource {
+    pub url: String,
+    /// Height in pixels if known
+    pub height: Option<u32>,
+    pub mimetype: Option<String>,
+}
+
+impl VideoSource {
+    fn is_mp4(&self) -> bool {
+        match &self.mimetype {
+            Some(mimetype) => mimetype.contains("mp4"),
+            None => self
+                .url
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .ends_with(".mp4"),
+        }
+    }
+}
+
+/// Where the media of a video page comes from
+#[derive(Debug, PartialEq)]
+pub enum VideoPage {
+    /// `<video>` sources embedded in the page
+    Direct(Vec<VideoSource>),
+    /// An episode served by Opencast, `episode_url` is its search endpoint
+    Opencast { episode_url: String },
+}
+
+/// Takes the height out of labels like `720p`, `1280x720` or `720`
+fn parse_height(label: &str) -> Option<u32> {
+    let label = label.trim().trim_end_matches(['p', 'P']);
+    let height = label.rsplit(['x', 'X']).next()?;
+    height.parse().ok()
+}
+
+/// Looks for `720p` style markers in a media url
+fn height_in_url(url: &str) -> Option<u32> {
+    url.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(|part| part.strip_suffix('p')?.parse().ok())
+        .find(|height| (144..=4320).contains(height))
+}
+
+/// Finds the media of the video page `html`, which was loaded from `page`
+pub fn parse_page(html: &Html, page: &str) -> Option<VideoPage> {
+    let page = Url::parse(page).ok()?;
+    let sources = html
+        .select(&SOURCES)
+        .filter_map(|source| {
+            let element = source.value();
+            let url = page.join(element.attr("src")?).ok()?.to_string();
+            let height = ["res", "data-res", "size", "label", "data-quality"]
+                .iter()
+                .find_map(|attr| parse_height(element.attr(attr)?))
+                .or_else(|| height_in_url(&url));
+            Some(VideoSource {
+                url,
+                height,
+                mimetype: element.attr("type").map(str::to_string),
+            })
+        })
+        .collect::<Vec<_>>();
+    if !sources.is_empty() {
+        return Some(VideoPage::Direct(sources));
+    }
+
+    // opencast players (paella, engage) are linked or embedded with the episode id
+    html.select(&EMBEDS).find_map(|embed| {
+        let link = embed
+            .value()
+            .attr("href")
+            .or_else(|| embed.value().attr("src"))?;
+        let url = page.join(link).ok()?;
+        if !(url.path().contains("paella") || url.path().contains("engage")) {
+            return None;
+        }
+        let (_, id) = url.query_pairs().find(|(key, _)| key == "id")?;
+        let mut episode = url.join("/search/episode.json").ok()?;
+        episode.query_pairs_mut().append_pair("id", &id);
+        Some(VideoPage::Opencast {
+            episode_url: episode.to_string(),
+        })
+    })
+}
+
+/// Opencast answers with an object where a list has only one entry
+fn one_or_many(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(values) => values.iter().collect(),
+        Value::Null => vec![],
+        value => vec![value],
+    }
+}
+
+/// Reads the answer of the Opencast search endpoint, both the old
+/// (`search-results.result`) and the new (`result`) layout
+pub fn parse_episode(json: &Value) -> Option<(VideoInfo, Vec<VideoSource>)> {
+    let result = json
+        .pointer("/search-results/result")
+        .or_else(|| json.get("result"))?;
+    let mediapackage = one_or_many(result).first()?.get("mediapackage")?;
+
+    let sources = one_or_many(mediapackage.pointer("/media/track").unwrap_or(&Value::Null))
+        .into_iter()
+        .filter_map(|track| {
+            Some(VideoSource {
+                url: track.get("url")?.as_str()?.to_string(),
+                height: track
+                    .pointer("/video/resolution")
+                    .and_then(Value::as_str)
+                    .and_then(parse_height),
+                mimetype: track
+                    .get("mimetype")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let duration = match mediapackage.get("duration") {
+        Some(Value::Number(millis)) => millis.as_u64(),
+        Some(Value::String(millis)) => millis.parse().ok(),
+        _ => None,
+    };
+    let presenter = one_or_many(
+        mediapackage
+            .pointer("/creators/creator")
+            .unwrap_or(&Value::Null),
+    )
+    .into_iter()
+    .filter_map(Value::as_str)
+    .collect::<Vec<_>>();
+    let info = VideoInfo {
+        duration: duration.map(|millis| millis / 1000),
+        recorded_at: mediapackage
+            .get("start")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        presenter: (!presenter.is_empty()).then(|| presenter.join(", ")),
+        qualities: qualities(&sources),
+    };
+    Some((info, sources))
+}
+
+/// Heights of the mp4 `sources`, highest first
+pub fn qualities(sources: &[VideoSource]) -> Vec<u32> {
+    let mut heights = sources
+        .iter()
+        .filter(|source| source.is_mp4())
+        .filter_map(|source| source.height)
+        .collect::<Vec<_>>();
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    heights.dedup();
+    heights
+}
+
+/// The mp4 source that matches `quality` best
+pub fn pick(sources: &[VideoSource], quality: Quality) -> Option<&VideoSource> {
+    let mp4 = sources.iter().filter(|source| source.is_mp4());
+    let height = |source: &&VideoSource| source.height.unwrap_or_default();
+    match quality {
+        Quality::Highest => mp4.max_by_key(height),
+        Quality::Lowest => mp4.min_by_key(height),
+        Quality::AtMost(max) => {
+            let sources = mp4.collect::<Vec<_>>();
+            sources
+                .iter()
+                .copied()
+                .filter(|source| height(source) <= max)
+                .max_by_key(height)
+                .or_else(|| sources.into_iter().min_by_key(height))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn source(url: &str, height: Option<u32>) -> VideoSource {
+        VideoSource {
+            url: url.to_string(),
+            height,
+            mimetype: None,
+        }
+    }
+
+    #[test]
+    fn parses_episodes_of_the_new_layout() {
+        let episode = json!({
+            "result": [{
+                "mediapackage": {
+                    "duration": 5_400_000,
+                    "start": "2024-04-15T08:15:00Z",
+                    "creators": { "creator": ["Prof. A", "Dr. B"] },
+                    "media": { "track": [
+                        {
+                            "url": "https://oc.example/720.mp4",
+                            "mimetype": "video/mp4",
+                            "video": { "resolution": "1280x720" }
+                        },
+                        {
+                            "url": "https://oc.example/master.m3u8",
+                            "mimetype": "application/x-mpegURL",
+                            "video": { "resolution": "1920x1080" }
+                        },
+                        {
+                            "url": "https://oc.example/360.mp4",
+                            "mimetype": "video/mp4",
+                            "video": { "resolution": "640x360" }
+                        }
+                    ]}
+                }
+            }]
+        });
+        let (info, sources) = parse_episode(&episode).unwrap();
+        assert_eq!(
+            info,
+            VideoInfo {
+                duration: Some(5400),
+                recorded_at: Some("2024-04-15T08:15:00Z".to_string()),
+                presenter: Some("Prof. A, Dr. B".to_string()),
+                qualities: vec![720, 360],
+            }
+        );
+        assert_eq!(sources.len(), 3);
+    }
+
+    #[test]
+    fn parses_episodes_of_the_old_layout_with_single_entries() {
+        let episode = json!({
+            "search-results": {
+                "result": {
+                    "mediapackage": {
+                        "duration": "61000",
+                        "creators": { "creator": "Prof. A" },
+                        "media": { "track": {
+                            "url": "https://oc.example/video.mp4",
+                            "video": { "resolution": "1920x1080" }
+                        }}
+                    }
+                }
+            }
+        });
+        let (info, sources) = parse_episode(&episode).unwrap();
+        assert_eq!(info.duration, Some(61));
+        assert_eq!(info.presenter.as_deref(), Some("Prof. A"));
+        assert_eq!(
+            sources,
+            [source("https://oc.example/video.mp4", Some(1080))]
+        );
+        assert_eq!(parse_episode(&json!({ "result": [] })), None);
+    }
+
+    #[test]
+    fn picks_the_source_matching_the_quality() {
+        let sources = [
+            source("https://oc.example/360.mp4", Some(360)),
+            source("https://oc.example/1080.mp4", Some(1080)),
+            source("https://oc.example/720.mp4", Some(720)),
+            source("https://oc.example/2160.webm", Some(2160)),
+        ];
+        let url = |quality| pick(&sources, quality).map(|source| source.url.as_str());
+        assert_eq!(url(Quality::Highest), Some("https://oc.example/1080.mp4"));
+        assert_eq!(url(Quality::Lowest), Some("https://oc.example/360.mp4"));
+        assert_eq!(
+            url(Quality::AtMost(800)),
+            Some("https://oc.example/720.mp4")
+        );
+        // nothing small enough falls back to the smallest
+        assert_eq!(
+            url(Quality::AtMost(240)),
+            Some("https://oc.example/360.mp4")
+        );
+        assert_eq!(pick(&sources[3..], Quality::Highest), None);
+    }
+
+    #[test]
+    fn finds_embedded_sources_and_opencast_players() {
+        let page = "https://ilias.example/ilias.php?cmd=showVideo";
+        let direct = Html::parse_document(
+            r#"<video><source src="media/lecture_720p.mp4" type="video/mp4"></video>"#,
+        );
+        assert_eq!(
+            parse_page(&direct, page),
+            Some(VideoPage::Direct(vec![VideoSource {
+                url: "https://ilias.example/media/lecture_720p.mp4".to_string(),
+                height: Some(720),
+                mimetype: Some("video/mp4".to_string()),
+            }]))
+        );
+
+        let opencast = Html::parse_document(
+            r#"<iframe src="https://oc.example/paella/ui/watch.html?id=abc-123"></iframe>"#,
+        );
+        assert_eq!(
+            parse_page(&opencast, page),
+            Some(VideoPage::Opencast {
+                episode_url: "https://oc.example/search/episode.json?id=abc-123".to_string()
+            })
+        );
+    }
+}